@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use tauri::AppHandle;
 
 use crate::ai;
@@ -6,6 +8,8 @@ use crate::db;
 use crate::models::*;
 use crate::redaction;
 use crate::runner;
+use crate::shell;
+use crate::templates;
 use crate::workflow;
 
 /// Application state
@@ -20,48 +24,204 @@ pub struct AppState {
 pub async fn nl_to_cmd(
     text: String,
     cwd: Option<String>,
+    join_commands: Option<bool>,
+    include_recent_history: Option<bool>,
 ) -> Result<AiCommandResponse, String> {
     // Check for dangerous patterns in user input
     if let Some(warning) = redaction::validate_command(&text) {
         return Ok(AiCommandResponse {
             commands: vec![],
+            alternatives: vec![],
             warning: Some(warning.reason),
             explanation: Some("The request contains potentially dangerous patterns.".to_string()),
+            confidence: None,
+            risk_level: Some(warning.severity),
+            provider: None,
+            pipeline: false,
         });
     }
 
-    ai::nl_to_cmd(&text, cwd.as_deref()).await
+    let mut response = ai::nl_to_cmd(
+        &text,
+        cwd.as_deref(),
+        include_recent_history.unwrap_or(false),
+    )
+    .await?;
+
+    // Combine into one runnable script for the configured shell
+    if join_commands.unwrap_or(false) {
+        let shell = runner::select_shell(None)?;
+        // Alternatives are joined the same way as the main answer
+        let pipeline = response.pipeline;
+        for commands in std::iter::once(&mut response.commands).chain(&mut response.alternatives) {
+            if commands.len() > 1 {
+                *commands = vec![if pipeline {
+                    shell::join_pipeline(commands)
+                } else {
+                    shell::join_commands(shell, commands)
+                }];
+            }
+        }
+    }
+
+    Ok(response)
 }
 
 // ============ Command Execution ============
 
 /// Run a shell command and stream output
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn run_command(
     app: AppHandle,
     command: String,
     cwd: Option<String>,
     generated_by_ai: Option<bool>,
     force: Option<bool>,
+    save_history: Option<bool>,
+    merge_output: Option<bool>,
+    dry_run: Option<bool>,
+    timeout_secs: Option<u64>,
+    env: Option<HashMap<String, String>>,
+    clear_env: Option<bool>,
+    shell: Option<String>,
+    interactive_input: Option<bool>,
+    path_override: Option<String>,
 ) -> Result<CommandHandle, String> {
+    check_command_allowed(&command, cwd.as_deref(), force.unwrap_or(false))?;
+
+    // Each run gets a fresh shell, so builtins like `cd` have no lasting effect
+    if let Some(builtin) = shell::state_only_builtin(&command) {
+        return Err(format!(
+            "'{}' only changes the state of the shell it runs in, and every command runs in a fresh shell, so it won't persist to later commands. Chain it with the command that needs it (e.g. `cd dir && npm test`), or use a workflow with a per-step cwd.",
+            builtin
+        ));
+    }
+
+    // Soft warning for network access, if the user opted in
+    let warn_on_network = db::get_preference_bool("warn_on_network", false);
+    let network_warning = if warn_on_network && redaction::is_network_command(&command) {
+        Some("This command accesses the network.".to_string())
+    } else {
+        None
+    };
+
+    if dry_run.unwrap_or(false) {
+        let mut handle = runner::dry_run_command(app, command, cwd, shell.as_deref())?;
+        handle.warning = network_warning;
+        return Ok(handle);
+    }
+
+    let mut handle = runner::run_command_emit(
+        app,
+        command,
+        cwd,
+        generated_by_ai.unwrap_or(false),
+        save_history.unwrap_or(true),
+        merge_output.unwrap_or(false),
+        timeout_secs,
+        env,
+        clear_env.unwrap_or(false),
+        shell.as_deref(),
+        interactive_input.unwrap_or(false),
+        path_override.as_deref(),
+    )
+    .await?;
+    handle.warning = network_warning;
+    Ok(handle)
+}
+
+/// Run a command and wait for its final exit code and output
+#[tauri::command]
+pub async fn run_command_blocking(
+    command: String,
+    cwd: Option<String>,
+    env: Option<HashMap<String, String>>,
+    timeout: Option<u64>,
+    force: Option<bool>,
+) -> Result<CommandOutput, String> {
+    check_command_allowed(&command, cwd.as_deref(), force.unwrap_or(false))?;
+
+    let history = CommandHistory {
+        id: None,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        command_text: command.clone(),
+        generated_by_ai: false,
+        cwd: cwd.clone(),
+        exit_code: None,
+        stdout: None,
+        stderr: None,
+        sensitive: false,
+    };
+    let id = db::insert_command_history(&history).map_err(|e| e.to_string())?;
+
+    let (exit_code, stdout, stderr) =
+        match runner::run_command_sync(&command, cwd.as_deref(), None, env.as_ref(), false, None, timeout).await {
+            Ok(result) => result,
+            Err(e) => {
+                // Keep failures to start queryable in history
+                let _ = db::update_command_history_output(
+                    id,
+                    None,
+                    Some(&e),
+                    Some(runner::SPAWN_FAILED_EXIT_CODE),
+                );
+                return Err(e);
+            }
+        };
+
+    let _ = db::update_command_history_output(id, Some(&stdout), Some(&stderr), Some(exit_code));
+
+    Ok(CommandOutput {
+        exit_code,
+        stdout,
+        stderr,
+    })
+}
+
+/// Reject dangerous or interactive commands before running them
+fn check_command_allowed(command: &str, cwd: Option<&str>, force: bool) -> Result<(), String> {
     // Check for dangerous commands
-    if let Some(warning) = redaction::validate_command(&command) {
-        if warning.severity == "high" && !force.unwrap_or(false) {
-            return Err(format!(
-                "Dangerous command blocked: {}. Use force=true to override.",
-                warning.reason
-            ));
+    if let Some(warning) = redaction::validate_command(command) {
+        if warning.severity == "high" {
+            let in_app_dir = context::is_app_directory(cwd.unwrap_or("."));
+
+            if !force {
+                let _ = db::insert_blocked_command(&BlockedCommand {
+                    id: None,
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                    command: command.to_string(),
+                    reason: warning.reason.clone(),
+                    severity: warning.severity.clone(),
+                });
+
+                if in_app_dir {
+                    return Err(format!(
+                        "Dangerous command blocked: {}. The working directory is Project Neural's own directory, so this command could modify or delete the app itself. Use force=true only if you really intend to change the app's files.",
+                        warning.reason
+                    ));
+                }
+                return Err(format!(
+                    "Dangerous command blocked: {}. Use force=true to override.",
+                    warning.reason
+                ));
+            }
+
+            if in_app_dir {
+                tracing::warn!("Forced dangerous command in the app's own directory: {}", command);
+            }
         }
     }
 
-    // Check for interactive commands
-    if redaction::is_interactive_command(&command) {
+    // Check for interactive commands, unless the user opted out of the block
+    let allow_interactive = db::get_preference_bool("allow_interactive_commands", false);
+    if !allow_interactive && redaction::is_interactive_command(command) {
         return Err(
             "Interactive commands are not supported in this terminal. Use a proper terminal emulator.".to_string()
         );
     }
 
-    runner::run_command_emit(app, command, cwd, generated_by_ai.unwrap_or(false)).await
+    Ok(())
 }
 
 /// Kill a running command
@@ -70,12 +230,63 @@ pub fn kill_command(id: i64) -> Result<(), String> {
     runner::kill_command(id)
 }
 
+/// Write text to a running command's stdin
+#[tauri::command]
+pub async fn send_stdin(id: i64, input: String) -> Result<(), String> {
+    runner::send_stdin(id, &input).await
+}
+
+/// Close a running command's stdin to signal end of input
+#[tauri::command]
+pub fn close_stdin(id: i64) -> Result<(), String> {
+    runner::close_stdin(id)
+}
+
+/// Join separate commands into a single pipeline
+#[tauri::command]
+pub fn join_as_pipeline(commands: Vec<String>) -> Result<String, String> {
+    let pipeline = shell::join_pipeline(&commands);
+    if pipeline.is_empty() {
+        return Err("No commands to join".to_string());
+    }
+    Ok(pipeline)
+}
+
+/// Quote an argument for the active shell, or the given one
+#[tauri::command]
+pub fn quote_for_shell(arg: String, shell: Option<String>) -> Result<String, String> {
+    let shell = runner::select_shell(shell.as_deref())?;
+    Ok(shell::quote_arg(shell, &arg))
+}
+
+/// Kill every running command
+#[tauri::command]
+pub fn kill_all_commands() -> Vec<i64> {
+    runner::kill_all_commands()
+}
+
+/// Stop all running commands, in-flight AI requests and workflows at once
+#[tauri::command]
+pub fn abort_all() -> AbortSummary {
+    AbortSummary {
+        commands_killed: runner::kill_all_commands(),
+        ai_requests_cancelled: ai::cancel_all_ai_requests(),
+        workflows_cancelled: workflow::cancel_all_workflows(),
+    }
+}
+
 /// Get list of running commands
 #[tauri::command]
 pub fn get_running_commands() -> Vec<i64> {
     runner::get_running_commands()
 }
 
+/// Stop tracking commands whose processes have already exited
+#[tauri::command]
+pub fn reap_finished_commands() -> Vec<i64> {
+    runner::reap_finished_commands()
+}
+
 // ============ Context ============
 
 /// Get project context for current directory
@@ -92,6 +303,29 @@ pub fn find_project_root(start: Option<String>) -> Option<String> {
     context::find_project_root(&dir)
 }
 
+/// Get recently used working directories with their project types, most recent first
+#[tauri::command]
+pub fn get_recent_projects(limit: Option<i32>) -> Result<Vec<RecentProject>, String> {
+    let cwds = db::get_recent_cwds(limit.unwrap_or(10)).map_err(|e| e.to_string())?;
+
+    Ok(cwds
+        .into_iter()
+        .map(|(cwd, last_used)| {
+            // Directories deleted since they were used have no type to detect
+            let project_type = if std::path::Path::new(&cwd).is_dir() {
+                context::scan_context(&cwd).project_type
+            } else {
+                None
+            };
+            RecentProject {
+                cwd,
+                project_type,
+                last_used,
+            }
+        })
+        .collect())
+}
+
 // ============ AI Features ============
 
 /// Analyze an error and get fix suggestions
@@ -105,13 +339,55 @@ pub async fn analyze_error(
     ai::analyze_error(&stderr, exit_code, &command, cwd.as_deref()).await
 }
 
+/// Explain an error in plain language without suggesting fixes
+#[tauri::command]
+pub async fn explain_error(
+    stderr: String,
+    command: String,
+    cwd: Option<String>,
+) -> Result<String, String> {
+    ai::explain_error(&stderr, &command, cwd.as_deref()).await
+}
+
 /// Explain a command in detail
 #[tauri::command]
 pub async fn explain_command(
+    app: AppHandle,
     command: String,
     cwd: Option<String>,
-) -> Result<AiExplanation, String> {
-    ai::explain_command(&command, cwd.as_deref()).await
+    explain_format: Option<String>,
+    stream_id: Option<String>,
+    audience: Option<String>,
+) -> Result<ExplanationOutput, String> {
+    // With a stream id, tokens are emitted as `ai_token` events tagged with it
+    let stream = stream_id.as_deref().map(|id| (&app, id));
+    let audience = audience.as_deref().unwrap_or("beginner");
+
+    match explain_format.as_deref().unwrap_or("structured") {
+        "structured" => ai::explain_command(&command, cwd.as_deref(), audience, stream)
+            .await
+            .map(ExplanationOutput::Structured),
+        "prose" => ai::explain_command_prose(&command, cwd.as_deref(), audience, stream)
+            .await
+            .map(ExplanationOutput::Prose),
+        _ => Err("explain_format must be 'structured' or 'prose'".to_string()),
+    }
+}
+
+/// Compare two commands and recommend one
+#[tauri::command]
+pub async fn compare_commands(
+    cmd_a: String,
+    cmd_b: String,
+    cwd: Option<String>,
+) -> Result<CommandComparison, String> {
+    ai::compare_commands(&cmd_a, &cmd_b, cwd.as_deref()).await
+}
+
+/// Get the kind of an AI error ("QuotaExceeded" or "BillingError") so the UI can guide the user
+#[tauri::command]
+pub fn get_ai_error_kind(error: String) -> Option<&'static str> {
+    ai::ai_error_kind(&error)
 }
 
 /// Check if AI is configured
@@ -138,16 +414,64 @@ pub fn set_openai_api_key(key: String) -> Result<(), String> {
     ai::set_openai_api_key(&key)
 }
 
-/// Set AI provider (gemini or openai)
+/// Set AI provider (gemini, openai or ollama)
 #[tauri::command]
 pub fn set_ai_provider(provider: String) -> Result<(), String> {
     ai::set_provider(&provider)
 }
 
+/// Switch AI provider and model together
+#[tauri::command]
+pub async fn set_ai_provider_and_model(provider: String, model: String) -> Result<AiConfig, String> {
+    ai::set_provider_and_model(&provider, &model).await
+}
+
+/// Get the selected AI provider and model
+#[tauri::command]
+pub fn get_ai_config() -> AiConfig {
+    ai::get_ai_config()
+}
+
 /// Set the AI model to use
 #[tauri::command]
-pub fn set_ai_model(model: String) -> Result<(), String> {
-    ai::set_model(&model)
+pub async fn set_ai_model(model: String) -> Result<(), String> {
+    ai::set_model(&model).await
+}
+
+/// List the models each AI provider offers
+#[tauri::command]
+pub async fn list_available_models() -> HashMap<String, Vec<String>> {
+    ai::list_available_models().await
+}
+
+/// Get the deprecated model names and what they migrate to
+#[tauri::command]
+pub fn get_model_migrations() -> HashMap<String, String> {
+    ai::get_model_migrations()
+}
+
+/// Get the endpoint AI requests are sent to
+#[tauri::command]
+pub fn get_effective_endpoints() -> AiEndpoints {
+    ai::get_effective_endpoints()
+}
+
+/// Export all stored AI prompts and responses to a JSON file
+#[tauri::command]
+pub fn export_ai_log(path: String, redacted: Option<bool>) -> Result<usize, String> {
+    ai::export_ai_log(&path, redacted.unwrap_or(false))
+}
+
+/// Get the features a model supports (defaults to the selected model)
+#[tauri::command]
+pub fn get_model_capabilities(model: Option<String>) -> ModelCapabilities {
+    ai::get_model_capabilities(model.as_deref())
+}
+
+/// Get suggested temperature, max_tokens and context window for a model
+#[tauri::command]
+pub fn get_model_defaults(model: String) -> ModelDefaults {
+    ai::get_model_defaults(&model)
 }
 
 /// Clear API key for current provider (to show setup screen again)
@@ -158,16 +482,26 @@ pub fn clear_api_key() -> Result<(), String> {
 
 // ============ Workflows ============
 
-/// Run a workflow
+/// Run a workflow, or with dry_run just list its steps
 #[tauri::command]
 pub async fn run_workflow(
     app: AppHandle,
     definition: serde_json::Value,
     cwd: Option<String>,
     workflow_id: Option<i64>,
+    total_timeout_secs: Option<u64>,
+    dry_run: Option<bool>,
 ) -> Result<WorkflowRunResult, String> {
     let steps = workflow::parse_workflow_steps(definition)?;
-    workflow::run_workflow(app, workflow_id, steps, cwd).await
+    workflow::run_workflow(
+        app,
+        workflow_id,
+        steps,
+        cwd,
+        total_timeout_secs,
+        dry_run.unwrap_or(false),
+    )
+    .await
 }
 
 /// Create a new workflow
@@ -180,19 +514,122 @@ pub fn create_workflow(
     workflow::create_workflow(&name, description.as_deref(), steps)
 }
 
+/// Update a saved workflow's name, description, and steps
+#[tauri::command]
+pub fn update_workflow(
+    id: i64,
+    name: String,
+    description: Option<String>,
+    steps: Vec<WorkflowStep>,
+) -> Result<(), String> {
+    workflow::update_workflow(id, &name, description.as_deref(), steps)
+}
+
+/// Delete a saved workflow
+#[tauri::command]
+pub fn delete_workflow(id: i64) -> Result<(), String> {
+    workflow::delete_workflow(id)
+}
+
+/// Export a saved workflow as portable JSON
+#[tauri::command]
+pub fn export_workflow(id: i64) -> Result<String, String> {
+    workflow::export_workflow(id)
+}
+
+/// Import a workflow from exported JSON, returning its new id
+#[tauri::command]
+pub fn import_workflow(
+    json: String,
+    cwd: Option<String>,
+    allow_external_paths: Option<bool>,
+) -> Result<i64, String> {
+    workflow::import_workflow(&json, cwd.as_deref(), allow_external_paths.unwrap_or(false))
+}
+
 /// Get all saved workflows
 #[tauri::command]
 pub fn get_workflows() -> Result<Vec<Workflow>, String> {
     workflow::get_workflows()
 }
 
+/// Get a saved workflow's definition JSON exactly as stored
+#[tauri::command]
+pub fn get_workflow_definition_raw(id: i64) -> Result<String, String> {
+    workflow::get_workflow_definition_raw(id)
+}
+
+/// Check a workflow definition for circular step dependencies, failing with the steps in any cycle
+#[tauri::command]
+pub fn validate_workflow_dependencies(definition: serde_json::Value) -> Result<(), String> {
+    let steps = workflow::parse_workflow_steps(definition)?;
+    workflow::validate_dependencies(&steps).map_err(|cycle| {
+        let cycle: Vec<String> = cycle.iter().map(|step| step.to_string()).collect();
+        format!("cycle: {}", cycle.join(", "))
+    })
+}
+
+/// Re-check a saved workflow's steps against the safety rules
+#[tauri::command]
+pub fn recheck_workflow_safety(id: i64) -> Result<Vec<StepWarning>, String> {
+    workflow::recheck_workflow_safety(id)
+}
+
+/// Get a stable signature of a saved workflow's steps
+#[tauri::command]
+pub fn workflow_signature(id: i64) -> Result<String, String> {
+    workflow::workflow_signature(id)
+}
+
 /// Generate a workflow from natural language
 #[tauri::command]
 pub async fn generate_workflow(
     description: String,
     cwd: Option<String>,
+    max_steps: Option<usize>,
 ) -> Result<Vec<WorkflowStep>, String> {
-    workflow::generate_workflow_from_nl(&description, cwd.as_deref()).await
+    workflow::generate_workflow_from_nl(&description, cwd.as_deref(), max_steps).await
+}
+
+/// Regenerate a single step of a workflow with AI
+#[tauri::command]
+pub async fn regenerate_workflow_step(
+    description: String,
+    existing_steps: Vec<WorkflowStep>,
+    step_number: i32,
+    cwd: Option<String>,
+) -> Result<WorkflowStep, String> {
+    ai::regenerate_step(&description, &existing_steps, step_number, cwd.as_deref()).await
+}
+
+// ============ Command Templates ============
+
+/// Save a named command template
+#[tauri::command]
+pub fn save_template(
+    name: String,
+    template: String,
+    description: Option<String>,
+) -> Result<i64, String> {
+    templates::save_template(&name, &template, description.as_deref())
+}
+
+/// Get all saved command templates
+#[tauri::command]
+pub fn get_templates() -> Result<Vec<CommandTemplate>, String> {
+    templates::get_templates()
+}
+
+/// Delete a command template
+#[tauri::command]
+pub fn delete_template(name: String) -> Result<(), String> {
+    templates::delete_template(&name)
+}
+
+/// Render a command template with parameters
+#[tauri::command]
+pub fn render_template(name: String, params: HashMap<String, String>) -> Result<String, String> {
+    templates::render_template(&name, &params)
 }
 
 // ============ History & Preferences ============
@@ -203,12 +640,177 @@ pub fn get_history(limit: Option<i32>, offset: Option<i32>) -> Result<Vec<Comman
     db::get_command_history(limit, offset).map_err(|e| e.to_string())
 }
 
+/// Search command history by command text and output
+#[tauri::command]
+pub fn search_command_history(
+    query: String,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> Result<Vec<CommandHistory>, String> {
+    db::search_command_history(&query, limit, offset).map_err(|e| e.to_string())
+}
+
+/// Get the most frequently run commands, grouping runs that differ only in paths or numbers
+#[tauri::command]
+pub fn get_top_commands(limit: Option<i32>) -> Result<Vec<TopCommand>, String> {
+    db::get_top_commands(limit.unwrap_or(10)).map_err(|e| e.to_string())
+}
+
+/// Get history totals, failure and AI-generated counts, and the 10 most frequent commands
+#[tauri::command]
+pub fn get_history_stats() -> Result<HistoryStats, String> {
+    db::get_history_stats(10).map_err(|e| e.to_string())
+}
+
+/// Get a history entry's stdout and stderr as a single log
+#[tauri::command]
+pub fn get_command_output(id: i64) -> Result<String, String> {
+    let entry = db::get_command_history_entry(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Command {} not found", id))?;
+
+    Ok(runner::combine_output(
+        entry.stdout.as_deref().unwrap_or(""),
+        entry.stderr.as_deref().unwrap_or(""),
+    ))
+}
+
+/// Mark a history entry as sensitive so it's never sent to the AI as context
+#[tauri::command]
+pub fn mark_command_sensitive(id: i64, sensitive: bool) -> Result<(), String> {
+    match db::set_command_sensitive(id, sensitive).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("Command {} not found", id)),
+    }
+}
+
+/// Delete a history entry along with its AI suggestions
+#[tauri::command]
+pub fn delete_command_history(id: i64) -> Result<(), String> {
+    match db::delete_command_history(id).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("Command {} not found", id)),
+    }
+}
+
+/// Delete all command history, returning how many entries were removed
+#[tauri::command]
+pub fn clear_command_history() -> Result<usize, String> {
+    db::clear_command_history().map_err(|e| e.to_string())
+}
+
+/// Check recent history for entries whose directory or program no longer exists
+#[tauri::command]
+pub fn audit_history_validity(limit: Option<i32>) -> Result<Vec<HistoryValidity>, String> {
+    let history = db::get_command_history(limit, None).map_err(|e| e.to_string())?;
+
+    Ok(history
+        .into_iter()
+        .filter_map(|entry| {
+            let cwd = entry.cwd.as_deref().map(std::path::Path::new);
+            let cwd_exists = cwd.map(|dir| dir.is_dir()).unwrap_or(true);
+            let program = shell::command_program(&entry.command_text);
+            let program_found = match &program {
+                Some(program) => shell::program_exists(
+                    program,
+                    cwd.filter(|_| cwd_exists).unwrap_or(std::path::Path::new(".")),
+                ),
+                None => true,
+            };
+
+            Some(HistoryValidity {
+                id: entry.id?,
+                cwd_exists,
+                program_found,
+                missing_program: program.filter(|_| !program_found),
+            })
+        })
+        .collect())
+}
+
+/// Get command history for commands run inside a project root
+#[tauri::command]
+pub fn get_history_for_project(
+    root: String,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> Result<Vec<CommandHistory>, String> {
+    db::get_command_history_for_project(&root, limit, offset).map_err(|e| e.to_string())
+}
+
 /// Get AI suggestions for a command
 #[tauri::command]
 pub fn get_suggestions_for_command(command_id: i64) -> Result<Vec<AiSuggestion>, String> {
     db::get_ai_suggestions_for_command(command_id).map_err(|e| e.to_string())
 }
 
+/// Get AI suggestions for a command, grouped by suggestion type
+#[tauri::command]
+pub fn get_suggestions_grouped(
+    command_id: i64,
+) -> Result<HashMap<String, Vec<AiSuggestion>>, String> {
+    let suggestions = db::get_ai_suggestions_for_command(command_id).map_err(|e| e.to_string())?;
+
+    let mut grouped: HashMap<String, Vec<AiSuggestion>> = HashMap::new();
+    for suggestion in suggestions {
+        grouped
+            .entry(suggestion.suggestion_type.clone())
+            .or_default()
+            .push(suggestion);
+    }
+    Ok(grouped)
+}
+
+/// Get commands that were blocked by the safety checks
+#[tauri::command]
+pub fn get_blocked_commands(limit: Option<i32>) -> Result<Vec<BlockedCommand>, String> {
+    db::get_blocked_commands(limit).map_err(|e| e.to_string())
+}
+
+/// Report suggestions with deleted history and history rows stuck without an exit code
+#[tauri::command]
+pub fn find_orphaned_records(older_than_hours: Option<i64>) -> Result<OrphanReport, String> {
+    db::find_orphaned_records(&orphan_cutoff(older_than_hours), &runner::get_running_commands())
+        .map_err(|e| e.to_string())
+}
+
+/// Remove dangling suggestions and close out stale history rows
+#[tauri::command]
+pub fn cleanup_orphaned_records(older_than_hours: Option<i64>) -> Result<OrphanReport, String> {
+    db::cleanup_orphaned_records(&orphan_cutoff(older_than_hours), &runner::get_running_commands())
+        .map_err(|e| e.to_string())
+}
+
+/// Timestamp before which unfinished history rows count as orphaned (default 24 hours ago)
+fn orphan_cutoff(older_than_hours: Option<i64>) -> String {
+    let hours = older_than_hours.unwrap_or(24).max(0);
+    (chrono::Utc::now() - chrono::Duration::hours(hours)).to_rfc3339()
+}
+
+/// Time representative history queries and report which indexes exist
+#[tauri::command]
+pub fn diagnose_db_performance() -> Result<DbPerformanceReport, String> {
+    db::diagnose_performance().map_err(|e| e.to_string())
+}
+
+/// Save a copy of the database to a new file
+#[tauri::command]
+pub fn backup_database(dest_path: String) -> Result<(), String> {
+    db::backup_database(std::path::Path::new(&dest_path)).map_err(|e| e.to_string())
+}
+
+/// Replace the database with a backup file
+#[tauri::command]
+pub fn restore_database(src_path: String) -> Result<(), String> {
+    db::restore_database(std::path::Path::new(&src_path)).map_err(|e| e.to_string())?;
+
+    // Redaction settings are cached, so reload them from the restored preferences
+    let pref = |key: &str| db::get_preference(key).ok().flatten().unwrap_or_else(|| "[]".to_string());
+    redaction::load_custom_patterns(&pref("custom_redaction_patterns"));
+    redaction::load_allowlist(&pref("redaction_allowlist"));
+    Ok(())
+}
+
 /// Get a preference value
 #[tauri::command]
 pub fn get_preference(key: String) -> Result<Option<String>, String> {
@@ -218,7 +820,55 @@ pub fn get_preference(key: String) -> Result<Option<String>, String> {
 /// Set a preference value
 #[tauri::command]
 pub fn set_preference(key: String, value: String) -> Result<(), String> {
-    db::set_preference(&key, &value).map_err(|e| e.to_string())
+    db::set_preference(&key, &value).map_err(|e| e.to_string())?;
+
+    // Redaction keeps compiled patterns, so pick up changes right away
+    match key.as_str() {
+        "custom_redaction_patterns" => {
+            redaction::load_custom_patterns(&value);
+        }
+        "redaction_allowlist" => {
+            redaction::load_allowlist(&value);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Get a boolean preference, or the default if unset
+#[tauri::command]
+pub fn get_preference_bool(key: String, default: bool) -> bool {
+    db::get_preference_bool(&key, default)
+}
+
+/// Get an integer preference, or the default if unset
+#[tauri::command]
+pub fn get_preference_i64(key: String, default: i64) -> i64 {
+    db::get_preference_i64(&key, default)
+}
+
+/// Get a floating-point preference, or the default if unset
+#[tauri::command]
+pub fn get_preference_f64(key: String, default: f64) -> f64 {
+    db::get_preference_f64(&key, default)
+}
+
+/// Set a boolean preference
+#[tauri::command]
+pub fn set_preference_bool(key: String, value: bool) -> Result<(), String> {
+    db::set_preference_bool(&key, value).map_err(|e| e.to_string())
+}
+
+/// Set an integer preference
+#[tauri::command]
+pub fn set_preference_i64(key: String, value: i64) -> Result<(), String> {
+    db::set_preference_i64(&key, value).map_err(|e| e.to_string())
+}
+
+/// Set a floating-point preference
+#[tauri::command]
+pub fn set_preference_f64(key: String, value: f64) -> Result<(), String> {
+    db::set_preference_f64(&key, value).map_err(|e| e.to_string())
 }
 
 /// Get all preferences
@@ -235,6 +885,21 @@ pub fn validate_command(command: String) -> Option<DangerWarning> {
     redaction::validate_command(&command)
 }
 
+/// Validate each segment of a compound command separately
+#[tauri::command]
+pub fn validate_pipeline(command: String) -> Vec<SegmentWarning> {
+    redaction::validate_pipeline(&command)
+}
+
+/// Check which existing files a command would overwrite before running it
+#[tauri::command]
+pub fn check_overwrites(command: String, cwd: Option<String>) -> OverwriteCheck {
+    let cwd = cwd.unwrap_or_else(|| ".".to_string());
+    OverwriteCheck {
+        would_overwrite: redaction::would_overwrite(&command, std::path::Path::new(&cwd)),
+    }
+}
+
 /// Check if a command is interactive
 #[tauri::command]
 pub fn is_interactive_command(command: String) -> bool {
@@ -247,4 +912,22 @@ pub fn redact_sensitive(text: String) -> String {
     redaction::redact_sensitive(&text)
 }
 
+/// List the kinds of secrets in a text, with how often each appears
+#[tauri::command]
+pub fn detect_secrets(text: String) -> Vec<SensitiveItem> {
+    redaction::detect_sensitive_items(&text)
+}
+
+/// Check that a custom redaction regex compiles before saving it
+#[tauri::command]
+pub fn validate_redaction_pattern(pattern: String) -> Result<(), String> {
+    redaction::validate_redaction_pattern(&pattern)
+}
+
+/// Find where each secret in a text is, as byte ranges
+#[tauri::command]
+pub fn locate_sensitive(text: String) -> Vec<SensitiveSpan> {
+    redaction::locate_sensitive(&text)
+}
+
 
@@ -1,6 +1,7 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::db;
 use crate::models::Context;
 
 /// Scan the directory for project context
@@ -18,7 +19,14 @@ pub fn scan_context(cwd: &str) -> Context {
     ctx.has_requirements_txt = path.join("requirements.txt").exists();
     ctx.has_manage_py = path.join("manage.py").exists();
     ctx.has_composer_json = path.join("composer.json").exists();
+    ctx.has_go_mod = path.join("go.mod").exists();
+    ctx.has_pom_xml = path.join("pom.xml").exists();
+    ctx.has_build_gradle =
+        path.join("build.gradle").exists() || path.join("build.gradle.kts").exists();
+    ctx.has_gemfile = path.join("Gemfile").exists();
+    ctx.has_dotnet_project = has_dotnet_project(path);
     ctx.has_git = path.join(".git").exists();
+    ctx.lockfile_conflict = detect_lockfiles(path).len() > 1;
     
     // Determine project type
     ctx.project_type = detect_project_type(&ctx);
@@ -43,11 +51,40 @@ fn detect_project_type(ctx: &Context) -> Option<String> {
         Some("Node.js".to_string())
     } else if ctx.has_composer_json {
         Some("PHP".to_string())
+    } else if ctx.has_go_mod {
+        Some("Go".to_string())
+    } else if ctx.has_pom_xml || ctx.has_build_gradle {
+        Some("Java".to_string())
+    } else if ctx.has_gemfile {
+        Some("Ruby".to_string())
+    } else if ctx.has_dotnet_project {
+        Some(".NET".to_string())
     } else {
         None
     }
 }
 
+/// Check for a .csproj or .sln file directly in the directory
+fn has_dotnet_project(path: &Path) -> bool {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.ends_with(".csproj") || name.ends_with(".sln")
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Find the JS package manager lockfiles present in a directory
+fn detect_lockfiles(path: &Path) -> Vec<&'static str> {
+    ["package-lock.json", "yarn.lock", "pnpm-lock.yaml", "bun.lockb"]
+        .into_iter()
+        .filter(|lockfile| path.join(lockfile).exists())
+        .collect()
+}
+
 /// Extract npm scripts from package.json
 fn extract_npm_scripts(path: &Path) -> Option<Vec<String>> {
     let package_json_path = path.join("package.json");
@@ -81,12 +118,23 @@ pub fn build_context_string(ctx: &Context) -> String {
     if ctx.has_requirements_txt { markers.push("requirements.txt"); }
     if ctx.has_manage_py { markers.push("manage.py"); }
     if ctx.has_composer_json { markers.push("composer.json"); }
+    if ctx.has_go_mod { markers.push("go.mod"); }
+    if ctx.has_pom_xml { markers.push("pom.xml"); }
+    if ctx.has_build_gradle { markers.push("build.gradle"); }
+    if ctx.has_gemfile { markers.push("Gemfile"); }
+    if ctx.has_dotnet_project { markers.push(".csproj/.sln"); }
     if ctx.has_git { markers.push(".git"); }
     
     if !markers.is_empty() {
         parts.push(format!("Project markers found: {}", markers.join(", ")));
     }
     
+    if ctx.lockfile_conflict {
+        parts.push(
+            "Warning: multiple package manager lockfiles found (e.g. package-lock.json and yarn.lock); point this out and use one package manager consistently".to_string(),
+        );
+    }
+    
     if let Some(ref scripts) = ctx.npm_scripts {
         if !scripts.is_empty() {
             parts.push(format!("Available npm scripts: {}", scripts.join(", ")));
@@ -125,6 +173,54 @@ pub fn find_project_root(start: &str) -> Option<String> {
     None
 }
 
+/// Canonicalize a path for display and process use, falling back to it unchanged
+pub fn normalize_path(path: &Path) -> String {
+    match path.canonicalize() {
+        Ok(resolved) => {
+            let resolved = resolved.to_string_lossy().to_string();
+            // Windows verbatim paths (\\?\C:\...) confuse many tools
+            match resolved.strip_prefix(r"\\?\") {
+                Some(stripped) if !stripped.starts_with("UNC") => stripped.to_string(),
+                _ => resolved,
+            }
+        }
+        Err(_) => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Check whether a path is the given root or inside it, comparing canonical paths
+pub fn is_within(path: &Path, root: &Path) -> bool {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    canonical(path).starts_with(canonical(root))
+}
+
+/// Check whether a directory is inside the app's own install or data directory
+pub fn is_app_directory(dir: &str) -> bool {
+    let resolved = match Path::new(dir).canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    app_directories().iter().any(|app_dir| resolved.starts_with(app_dir))
+}
+
+/// Directories holding the app's own files
+fn app_directories() -> Vec<PathBuf> {
+    let mut dirs = vec![db::get_data_dir()];
+
+    if let Ok(exe) = std::env::current_exe() {
+        if let Some(exe_dir) = exe.parent() {
+            dirs.push(exe_dir.to_path_buf());
+        }
+    }
+
+    // In development the app runs from its source tree
+    #[cfg(debug_assertions)]
+    dirs.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+
+    dirs.into_iter().filter_map(|d| d.canonicalize().ok()).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,6 +232,31 @@ mod tests {
         let ctx = scan_context(cwd.to_str().unwrap());
         assert!(!ctx.cwd.is_empty());
     }
+
+    #[test]
+    fn test_scan_context_polyglot_markers() {
+        let dir = env::temp_dir().join(format!("neural-context-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("go.mod"), "module example.com/app\n").unwrap();
+        fs::write(dir.join("App.csproj"), "<Project />").unwrap();
+
+        let ctx = scan_context(dir.to_str().unwrap());
+        assert!(ctx.has_go_mod);
+        assert!(ctx.has_dotnet_project);
+        assert!(!ctx.has_pom_xml && !ctx.has_build_gradle && !ctx.has_gemfile);
+        assert_eq!(ctx.project_type.as_deref(), Some("Go"));
+        assert!(build_context_string(&ctx).contains("go.mod, .csproj/.sln"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_is_within() {
+        let cwd = env::current_dir().unwrap();
+        assert!(is_within(&cwd.join("src").join(".."), &cwd));
+        assert!(is_within(&cwd, &cwd));
+        assert!(!is_within(cwd.parent().unwrap(), &cwd));
+    }
 }
 
 
@@ -1,28 +1,54 @@
 use anyhow::Result;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use rusqlite::Connection;
-use std::path::PathBuf;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use crate::models::{AiSuggestion, CommandHistory, Preference, Workflow};
+use crate::models::{
+    AiSuggestion, BlockedCommand, CommandCount, CommandHistory, CommandTemplate,
+    DbPerformanceReport, HistoryStats, IndexStatus, OrphanReport, Preference, QueryTiming,
+    TopCommand, Workflow,
+};
+use crate::context;
+use crate::shell;
 
 static DB: OnceCell<Mutex<Connection>> = OnceCell::new();
 
-/// Get the database path
-fn get_db_path() -> PathBuf {
+/// Get the app's data directory
+pub fn get_data_dir() -> PathBuf {
     let app_dir = dirs::data_local_dir()
         .unwrap_or_else(|| PathBuf::from("."))
         .join("project-neural");
     std::fs::create_dir_all(&app_dir).ok();
-    app_dir.join("neural.db")
+    app_dir
+}
+
+/// Get the database path
+fn get_db_path() -> PathBuf {
+    get_data_dir().join("neural.db")
 }
 
 /// Initialize the database connection and create tables
 pub fn init_db() -> Result<()> {
-    let db_path = get_db_path();
-    let conn = Connection::open(&db_path)?;
-    
-    // Create tables
+    let conn = open_database(&get_db_path())?;
+
+    DB.set(Mutex::new(conn))
+        .map_err(|_| anyhow::anyhow!("Database already initialized"))?;
+
+    Ok(())
+}
+
+/// Open a database file and bring it up to the current schema
+fn open_database(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    create_tables(&conn)?;
+    run_migrations(&conn)?;
+    Ok(conn)
+}
+
+/// Create any missing tables with their current columns
+fn create_tables(conn: &Connection) -> Result<()> {
     conn.execute_batch(
         r#"
         CREATE TABLE IF NOT EXISTS commands_history (
@@ -33,7 +59,9 @@ pub fn init_db() -> Result<()> {
             cwd TEXT,
             exit_code INTEGER,
             stdout TEXT,
-            stderr TEXT
+            stderr TEXT,
+            sensitive INTEGER DEFAULT 0,
+            fingerprint TEXT
         );
 
         CREATE TABLE IF NOT EXISTS ai_suggestions (
@@ -60,14 +88,127 @@ pub fn init_db() -> Result<()> {
             value TEXT NOT NULL
         );
 
+        CREATE TABLE IF NOT EXISTS blocked_commands (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            command TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            severity TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS command_templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            template TEXT NOT NULL,
+            description TEXT,
+            created_at TEXT
+        );
+
         CREATE INDEX IF NOT EXISTS idx_commands_timestamp ON commands_history(timestamp);
         CREATE INDEX IF NOT EXISTS idx_ai_suggestions_created ON ai_suggestions(created_at);
         "#,
     )?;
+    Ok(())
+}
 
-    DB.set(Mutex::new(conn))
-        .map_err(|_| anyhow::anyhow!("Database already initialized"))?;
+/// A schema change applied to databases created by older versions
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema changes in order; after running the nth, `PRAGMA user_version` is n.
+/// Tables created fresh already have these columns, so each must be idempotent.
+fn migrations() -> Vec<Migration> {
+    vec![
+        |conn| add_column_if_missing(conn, "commands_history", "sensitive", "INTEGER DEFAULT 0"),
+        |conn| {
+            add_column_if_missing(conn, "commands_history", "fingerprint", "TEXT")?;
+            backfill_fingerprints(conn)?;
+            conn.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_commands_fingerprint ON commands_history(fingerprint);",
+            )?;
+            Ok(())
+        },
+        backfill_cwds,
+    ]
+}
+
+/// Apply the migrations the database hasn't seen yet, bumping user_version after each
+fn run_migrations(conn: &Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let migrations = migrations();
+    if version > migrations.len() {
+        anyhow::bail!(
+            "Database schema version {} is newer than this app supports ({})",
+            version,
+            migrations.len()
+        );
+    }
+
+    for (index, migration) in migrations.iter().enumerate().skip(version) {
+        conn.execute_batch("BEGIN")?;
+        let applied = migration(conn).and_then(|_| {
+            conn.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+            Ok(())
+        });
+        match applied {
+            Ok(()) => conn.execute_batch("COMMIT")?,
+            Err(e) => {
+                let _ = conn.execute_batch("ROLLBACK");
+                return Err(e.context(format!("Database migration {} failed", index + 1)));
+            }
+        }
+        tracing::info!("Applied database migration {}", index + 1);
+    }
+    Ok(())
+}
+
+/// Add a column to an existing table unless it's already there
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+    }
+    Ok(())
+}
+
+/// Fingerprint history rows recorded before fingerprints were stored
+fn backfill_fingerprints(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT id, command_text FROM commands_history WHERE fingerprint IS NULL")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (id, command) in rows {
+        conn.execute(
+            "UPDATE commands_history SET fingerprint = ?1 WHERE id = ?2",
+            (shell::command_fingerprint(&command), id),
+        )?;
+    }
+    Ok(())
+}
+
+/// Canonicalize the cwd of rows stored before cwds were normalized on insert
+fn backfill_cwds(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "commands_history", "cwd", "TEXT")?;
+    let mut stmt = conn.prepare("SELECT id, cwd FROM commands_history WHERE cwd IS NOT NULL")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
 
+    for (id, cwd) in rows {
+        let normalized = context::normalize_path(Path::new(&cwd));
+        if normalized != cwd {
+            conn.execute(
+                "UPDATE commands_history SET cwd = ?1 WHERE id = ?2",
+                (normalized, id),
+            )?;
+        }
+    }
     Ok(())
 }
 
@@ -82,12 +223,15 @@ fn get_db() -> &'static Mutex<Connection> {
 pub fn insert_command_history(cmd: &CommandHistory) -> Result<i64> {
     let conn = get_db().lock();
     conn.execute(
-        "INSERT INTO commands_history (timestamp, command_text, generated_by_ai, cwd) VALUES (?1, ?2, ?3, ?4)",
+        "INSERT INTO commands_history (timestamp, command_text, generated_by_ai, cwd, fingerprint)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
         (
             &cmd.timestamp,
             &cmd.command_text,
             cmd.generated_by_ai as i32,
-            &cmd.cwd,
+            // Stored canonical so project history can match it by prefix
+            cmd.cwd.as_deref().map(|cwd| context::normalize_path(Path::new(cwd))),
+            shell::command_fingerprint(&cmd.command_text),
         ),
     )?;
     Ok(conn.last_insert_rowid())
@@ -108,6 +252,16 @@ pub fn update_command_history_output(
     Ok(())
 }
 
+/// Update only the exit code of a command history entry
+pub fn update_command_history_exit_code(id: i64, exit_code: i32) -> Result<()> {
+    let conn = get_db().lock();
+    conn.execute(
+        "UPDATE commands_history SET exit_code = ?1 WHERE id = ?2",
+        (exit_code, id),
+    )?;
+    Ok(())
+}
+
 /// Get command history with pagination
 pub fn get_command_history(limit: Option<i32>, offset: Option<i32>) -> Result<Vec<CommandHistory>> {
     let conn = get_db().lock();
@@ -115,23 +269,179 @@ pub fn get_command_history(limit: Option<i32>, offset: Option<i32>) -> Result<Ve
     let offset = offset.unwrap_or(0);
 
     let mut stmt = conn.prepare(
-        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr 
+        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
          FROM commands_history ORDER BY timestamp DESC LIMIT ?1 OFFSET ?2",
     )?;
 
-    let rows = stmt.query_map([limit, offset], |row| {
-        Ok(CommandHistory {
-            id: Some(row.get(0)?),
-            timestamp: row.get(1)?,
-            command_text: row.get(2)?,
-            generated_by_ai: row.get::<_, i32>(3)? != 0,
-            cwd: row.get(4)?,
-            exit_code: row.get(5)?,
-            stdout: row.get(6)?,
-            stderr: row.get(7)?,
+    let rows = stmt.query_map([limit, offset], history_from_row)?;
+
+    let mut history = Vec::new();
+    for row in rows {
+        history.push(row?);
+    }
+    Ok(history)
+}
+
+/// Search history for a substring of the command, stdout, or stderr, newest first
+pub fn search_command_history(
+    query: &str,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> Result<Vec<CommandHistory>> {
+    let conn = get_db().lock();
+    let limit = limit.unwrap_or(100);
+    let offset = offset.unwrap_or(0);
+    let pattern = format!("%{}%", escape_like(query));
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
+         FROM commands_history
+         WHERE command_text LIKE ?1 ESCAPE '\\'
+            OR stdout LIKE ?1 ESCAPE '\\'
+            OR stderr LIKE ?1 ESCAPE '\\'
+         ORDER BY timestamp DESC LIMIT ?2 OFFSET ?3",
+    )?;
+
+    let rows = stmt.query_map((pattern, limit, offset), history_from_row)?;
+
+    let mut history = Vec::new();
+    for row in rows {
+        history.push(row?);
+    }
+    Ok(history)
+}
+
+/// Escape LIKE wildcards so a query matches literally
+fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Get the most frequently run commands, grouped by fingerprint
+pub fn get_top_commands(limit: i32) -> Result<Vec<TopCommand>> {
+    let conn = get_db().lock();
+
+    // SQLite takes the bare command_text from the row holding MAX(timestamp)
+    let mut stmt = conn.prepare(
+        "SELECT fingerprint, command_text, COUNT(*) AS uses, MAX(timestamp)
+         FROM commands_history WHERE fingerprint IS NOT NULL
+         GROUP BY fingerprint ORDER BY uses DESC LIMIT ?1",
+    )?;
+
+    let rows = stmt.query_map([limit], |row| {
+        Ok(TopCommand {
+            fingerprint: row.get(0)?,
+            latest_command: row.get(1)?,
+            count: row.get(2)?,
+            last_used: row.get(3)?,
         })
     })?;
 
+    let mut commands = Vec::new();
+    for row in rows {
+        commands.push(row?);
+    }
+    Ok(commands)
+}
+
+/// Count total, failed, and AI-generated commands plus the most frequent command texts
+pub fn get_history_stats(top_limit: i32) -> Result<HistoryStats> {
+    let conn = get_db().lock();
+
+    let (total, failures, ai_generated) = conn.query_row(
+        "SELECT COUNT(*),
+                COALESCE(SUM(exit_code IS NOT NULL AND exit_code != 0), 0),
+                COALESCE(SUM(generated_by_ai != 0), 0)
+         FROM commands_history",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT command_text, COUNT(*) AS uses FROM commands_history
+         GROUP BY command_text ORDER BY uses DESC, MAX(timestamp) DESC LIMIT ?1",
+    )?;
+    let top_commands = stmt
+        .query_map([top_limit], |row| {
+            Ok(CommandCount {
+                command_text: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(HistoryStats {
+        total,
+        failures,
+        ai_generated,
+        top_commands,
+    })
+}
+
+/// Get a single history entry by id
+pub fn get_command_history_entry(id: i64) -> Result<Option<CommandHistory>> {
+    let conn = get_db().lock();
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
+         FROM commands_history WHERE id = ?1",
+    )?;
+
+    let mut rows = stmt.query_map([id], history_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+/// Get history for commands run in a directory or anywhere beneath it, newest first
+pub fn get_command_history_for_project(
+    root: &str,
+    limit: Option<i32>,
+    offset: Option<i32>,
+) -> Result<Vec<CommandHistory>> {
+    let conn = get_db().lock();
+    query_history_for_project(&conn, root, limit.unwrap_or(100), offset.unwrap_or(0))
+}
+
+/// Match history under the canonical root; cwds are stored canonical by `insert_command_history`
+fn query_history_for_project(
+    conn: &Connection,
+    root: &str,
+    limit: i32,
+    offset: i32,
+) -> Result<Vec<CommandHistory>> {
+    let root = context::normalize_path(Path::new(root));
+    let root = root.trim_end_matches(['/', '\\']);
+    let beneath = format!("{}{}", root, std::path::MAIN_SEPARATOR);
+
+    // substr keeps the prefix test case-sensitive, unlike LIKE
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
+         FROM commands_history
+         WHERE cwd = ?1 OR substr(cwd, 1, length(?2)) = ?2
+         ORDER BY timestamp DESC LIMIT ?3 OFFSET ?4",
+    )?;
+
+    let rows = stmt.query_map((root, beneath, limit, offset), history_from_row)?;
+
+    let mut history = Vec::new();
+    for row in rows {
+        history.push(row?);
+    }
+    Ok(history)
+}
+
+/// Get the most recent history entries that may be shared with the AI, newest first
+pub fn get_shareable_history(limit: i32) -> Result<Vec<CommandHistory>> {
+    let conn = get_db().lock();
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
+         FROM commands_history WHERE COALESCE(sensitive, 0) = 0 ORDER BY timestamp DESC LIMIT ?1",
+    )?;
+
+    let rows = stmt.query_map([limit], history_from_row)?;
+
     let mut history = Vec::new();
     for row in rows {
         history.push(row?);
@@ -139,6 +449,68 @@ pub fn get_command_history(limit: Option<i32>, offset: Option<i32>) -> Result<Ve
     Ok(history)
 }
 
+/// Flag or unflag a history entry as sensitive, returning whether it exists
+pub fn set_command_sensitive(id: i64, sensitive: bool) -> Result<bool> {
+    let conn = get_db().lock();
+    let updated = conn.execute(
+        "UPDATE commands_history SET sensitive = ?1 WHERE id = ?2",
+        (sensitive as i32, id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// Delete a history entry and its linked AI suggestions, returning whether it existed
+pub fn delete_command_history(id: i64) -> Result<bool> {
+    let mut conn = get_db().lock();
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM ai_suggestions WHERE command_history_id = ?1", [id])?;
+    let deleted = tx.execute("DELETE FROM commands_history WHERE id = ?1", [id])?;
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+/// Delete all history and every AI suggestion linked to it, returning how many entries were removed
+pub fn clear_command_history() -> Result<usize> {
+    let mut conn = get_db().lock();
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM ai_suggestions WHERE command_history_id IS NOT NULL", [])?;
+    let deleted = tx.execute("DELETE FROM commands_history", [])?;
+    tx.commit()?;
+    Ok(deleted)
+}
+
+/// Get the most recently used distinct working directories with when each was last used
+pub fn get_recent_cwds(limit: i32) -> Result<Vec<(String, String)>> {
+    let conn = get_db().lock();
+
+    let mut stmt = conn.prepare(
+        "SELECT cwd, MAX(timestamp) AS last_used FROM commands_history
+         WHERE cwd IS NOT NULL AND cwd != '' GROUP BY cwd ORDER BY last_used DESC LIMIT ?1",
+    )?;
+
+    let rows = stmt.query_map([limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    let mut cwds = Vec::new();
+    for row in rows {
+        cwds.push(row?);
+    }
+    Ok(cwds)
+}
+
+fn history_from_row(row: &rusqlite::Row) -> rusqlite::Result<CommandHistory> {
+    Ok(CommandHistory {
+        id: Some(row.get(0)?),
+        timestamp: row.get(1)?,
+        command_text: row.get(2)?,
+        generated_by_ai: row.get::<_, i32>(3)? != 0,
+        cwd: row.get(4)?,
+        exit_code: row.get(5)?,
+        stdout: row.get(6)?,
+        stderr: row.get(7)?,
+        sensitive: row.get::<_, Option<i32>>(8)?.unwrap_or(0) != 0,
+    })
+}
+
 // ============ AI Suggestions Operations ============
 
 /// Insert a new AI suggestion
@@ -166,16 +538,7 @@ pub fn get_ai_suggestions_for_command(command_history_id: i64) -> Result<Vec<AiS
          FROM ai_suggestions WHERE command_history_id = ?1 ORDER BY created_at DESC",
     )?;
 
-    let rows = stmt.query_map([command_history_id], |row| {
-        Ok(AiSuggestion {
-            id: Some(row.get(0)?),
-            created_at: row.get(1)?,
-            prompt: row.get(2)?,
-            response: row.get(3)?,
-            suggestion_type: row.get(4)?,
-            command_history_id: row.get(5)?,
-        })
-    })?;
+    let rows = stmt.query_map([command_history_id], suggestion_from_row)?;
 
     let mut suggestions = Vec::new();
     for row in rows {
@@ -184,6 +547,34 @@ pub fn get_ai_suggestions_for_command(command_history_id: i64) -> Result<Vec<AiS
     Ok(suggestions)
 }
 
+/// Get every AI suggestion, oldest first
+pub fn get_all_ai_suggestions() -> Result<Vec<AiSuggestion>> {
+    let conn = get_db().lock();
+    let mut stmt = conn.prepare(
+        "SELECT id, created_at, prompt, response, type, command_history_id
+         FROM ai_suggestions ORDER BY created_at ASC",
+    )?;
+
+    let rows = stmt.query_map([], suggestion_from_row)?;
+
+    let mut suggestions = Vec::new();
+    for row in rows {
+        suggestions.push(row?);
+    }
+    Ok(suggestions)
+}
+
+fn suggestion_from_row(row: &rusqlite::Row) -> rusqlite::Result<AiSuggestion> {
+    Ok(AiSuggestion {
+        id: Some(row.get(0)?),
+        created_at: row.get(1)?,
+        prompt: row.get(2)?,
+        response: row.get(3)?,
+        suggestion_type: row.get(4)?,
+        command_history_id: row.get(5)?,
+    })
+}
+
 // ============ Workflows Operations ============
 
 /// Insert a new workflow
@@ -208,17 +599,7 @@ pub fn get_workflows() -> Result<Vec<Workflow>> {
         "SELECT id, name, description, definition, created_at, last_run_at FROM workflows ORDER BY name",
     )?;
 
-    let rows = stmt.query_map([], |row| {
-        let def_str: String = row.get(3)?;
-        Ok(Workflow {
-            id: Some(row.get(0)?),
-            name: row.get(1)?,
-            description: row.get(2)?,
-            definition: serde_json::from_str(&def_str).unwrap_or(serde_json::Value::Null),
-            created_at: row.get(4)?,
-            last_run_at: row.get(5)?,
-        })
-    })?;
+    let rows = stmt.query_map([], workflow_from_row)?;
 
     let mut workflows = Vec::new();
     for row in rows {
@@ -227,6 +608,65 @@ pub fn get_workflows() -> Result<Vec<Workflow>> {
     Ok(workflows)
 }
 
+/// Get a single workflow by id
+pub fn get_workflow(id: i64) -> Result<Option<Workflow>> {
+    let conn = get_db().lock();
+    let mut stmt = conn.prepare(
+        "SELECT id, name, description, definition, created_at, last_run_at FROM workflows WHERE id = ?1",
+    )?;
+    match stmt.query_row([id], workflow_from_row) {
+        Ok(workflow) => Ok(Some(workflow)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get a workflow's definition exactly as stored
+pub fn get_workflow_definition_raw(id: i64) -> Result<Option<String>> {
+    let conn = get_db().lock();
+    let mut stmt = conn.prepare("SELECT definition FROM workflows WHERE id = ?1")?;
+    match stmt.query_row([id], |row| row.get(0)) {
+        Ok(definition) => Ok(Some(definition)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Map a workflows row to a Workflow
+fn workflow_from_row(row: &rusqlite::Row) -> rusqlite::Result<Workflow> {
+    let def_str: String = row.get(3)?;
+    Ok(Workflow {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        description: row.get(2)?,
+        definition: serde_json::from_str(&def_str).unwrap_or(serde_json::Value::Null),
+        created_at: row.get(4)?,
+        last_run_at: row.get(5)?,
+    })
+}
+
+/// Replace a workflow's name, description, and definition, returning whether it existed
+pub fn update_workflow(
+    id: i64,
+    name: &str,
+    description: Option<&str>,
+    definition: &serde_json::Value,
+) -> Result<bool> {
+    let conn = get_db().lock();
+    let updated = conn.execute(
+        "UPDATE workflows SET name = ?1, description = ?2, definition = ?3 WHERE id = ?4",
+        (name, description, definition.to_string(), id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// Delete a workflow by id, returning whether it existed
+pub fn delete_workflow(id: i64) -> Result<bool> {
+    let conn = get_db().lock();
+    let deleted = conn.execute("DELETE FROM workflows WHERE id = ?1", [id])?;
+    Ok(deleted > 0)
+}
+
 /// Update workflow last run time
 pub fn update_workflow_last_run(id: i64, last_run_at: &str) -> Result<()> {
     let conn = get_db().lock();
@@ -237,6 +677,357 @@ pub fn update_workflow_last_run(id: i64, last_run_at: &str) -> Result<()> {
     Ok(())
 }
 
+// ============ Blocked Commands Operations ============
+
+/// Record a command that was blocked by the safety checks
+pub fn insert_blocked_command(blocked: &BlockedCommand) -> Result<i64> {
+    let conn = get_db().lock();
+    conn.execute(
+        "INSERT INTO blocked_commands (timestamp, command, reason, severity) VALUES (?1, ?2, ?3, ?4)",
+        (
+            &blocked.timestamp,
+            &blocked.command,
+            &blocked.reason,
+            &blocked.severity,
+        ),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Get the most recently blocked commands
+pub fn get_blocked_commands(limit: Option<i32>) -> Result<Vec<BlockedCommand>> {
+    let conn = get_db().lock();
+    let limit = limit.unwrap_or(100);
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, command, reason, severity
+         FROM blocked_commands ORDER BY timestamp DESC LIMIT ?1",
+    )?;
+
+    let rows = stmt.query_map([limit], |row| {
+        Ok(BlockedCommand {
+            id: Some(row.get(0)?),
+            timestamp: row.get(1)?,
+            command: row.get(2)?,
+            reason: row.get(3)?,
+            severity: row.get(4)?,
+        })
+    })?;
+
+    let mut blocked = Vec::new();
+    for row in rows {
+        blocked.push(row?);
+    }
+    Ok(blocked)
+}
+
+// ============ Command Template Operations ============
+
+/// Insert a template, or update the existing one with the same name
+pub fn upsert_command_template(template: &CommandTemplate) -> Result<i64> {
+    let conn = get_db().lock();
+    conn.execute(
+        "INSERT INTO command_templates (name, template, description, created_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET template = excluded.template, description = excluded.description",
+        (
+            &template.name,
+            &template.template,
+            &template.description,
+            &template.created_at,
+        ),
+    )?;
+
+    let id = conn.query_row(
+        "SELECT id FROM command_templates WHERE name = ?1",
+        [&template.name],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+/// Get all command templates, sorted by name
+pub fn get_command_templates() -> Result<Vec<CommandTemplate>> {
+    let conn = get_db().lock();
+    let mut stmt = conn.prepare(
+        "SELECT id, name, template, description, created_at FROM command_templates ORDER BY name",
+    )?;
+
+    let rows = stmt.query_map([], template_from_row)?;
+
+    let mut templates = Vec::new();
+    for row in rows {
+        templates.push(row?);
+    }
+    Ok(templates)
+}
+
+/// Get a command template by name
+pub fn get_command_template(name: &str) -> Result<Option<CommandTemplate>> {
+    let conn = get_db().lock();
+    let mut stmt = conn.prepare(
+        "SELECT id, name, template, description, created_at FROM command_templates WHERE name = ?1",
+    )?;
+
+    match stmt.query_row([name], template_from_row) {
+        Ok(template) => Ok(Some(template)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete a command template by name, returning whether it existed
+pub fn delete_command_template(name: &str) -> Result<bool> {
+    let conn = get_db().lock();
+    let deleted = conn.execute("DELETE FROM command_templates WHERE name = ?1", [name])?;
+    Ok(deleted > 0)
+}
+
+fn template_from_row(row: &rusqlite::Row) -> rusqlite::Result<CommandTemplate> {
+    Ok(CommandTemplate {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        template: row.get(2)?,
+        description: row.get(3)?,
+        placeholders: Vec::new(),
+        created_at: row.get(4)?,
+    })
+}
+
+// ============ Maintenance Operations ============
+
+/// Exit code recorded for history rows that never finished
+pub const ABANDONED_EXIT_CODE: i32 = -1;
+
+/// Find suggestions pointing at deleted history rows and history rows stuck without an exit code
+pub fn find_orphaned_records(older_than: &str, running: &[i64]) -> Result<OrphanReport> {
+    let conn = get_db().lock();
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM ai_suggestions
+         WHERE command_history_id IS NOT NULL
+           AND command_history_id NOT IN (SELECT id FROM commands_history)",
+    )?;
+    let dangling_suggestions = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM commands_history WHERE exit_code IS NULL AND timestamp < ?1",
+    )?;
+    let stale_history = stmt
+        .query_map([older_than], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?
+        .into_iter()
+        .filter(|id| !running.contains(id))
+        .collect();
+
+    Ok(OrphanReport {
+        dangling_suggestions,
+        stale_history,
+    })
+}
+
+/// Delete dangling suggestions and close out stale history rows, returning what was fixed
+pub fn cleanup_orphaned_records(older_than: &str, running: &[i64]) -> Result<OrphanReport> {
+    let report = find_orphaned_records(older_than, running)?;
+    let mut conn = get_db().lock();
+    let tx = conn.transaction()?;
+
+    for id in &report.dangling_suggestions {
+        tx.execute("DELETE FROM ai_suggestions WHERE id = ?1", [id])?;
+    }
+    for id in &report.stale_history {
+        tx.execute(
+            "UPDATE commands_history SET exit_code = ?1 WHERE id = ?2 AND exit_code IS NULL",
+            (ABANDONED_EXIT_CODE, id),
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Indexes the history and suggestion queries depend on, as (name, table)
+const EXPECTED_INDEXES: &[(&str, &str)] = &[
+    ("idx_commands_timestamp", "commands_history"),
+    ("idx_ai_suggestions_created", "ai_suggestions"),
+    ("idx_commands_fingerprint", "commands_history"),
+];
+
+/// Time representative queries and check that the expected indexes exist
+pub fn diagnose_performance() -> Result<DbPerformanceReport> {
+    let conn = get_db().lock();
+
+    let history_rows = conn.query_row("SELECT COUNT(*) FROM commands_history", [], |row| row.get(0))?;
+
+    let queries = [
+        (
+            "history_page",
+            "SELECT id, timestamp, command_text, generated_by_ai, cwd, exit_code, stdout, stderr, sensitive
+             FROM commands_history ORDER BY timestamp DESC LIMIT 100",
+        ),
+        (
+            "search",
+            "SELECT id FROM commands_history WHERE command_text LIKE '%git%'
+             ORDER BY timestamp DESC LIMIT 100",
+        ),
+        (
+            "top_commands",
+            "SELECT fingerprint, COUNT(*) AS uses FROM commands_history
+             GROUP BY fingerprint ORDER BY uses DESC LIMIT 10",
+        ),
+    ];
+
+    let mut timings = Vec::new();
+    for (name, sql) in queries {
+        let start = Instant::now();
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+        let mut count = 0;
+        while rows.next()?.is_some() {
+            count += 1;
+        }
+        timings.push(QueryTiming {
+            name: name.to_string(),
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+            rows: count,
+        });
+    }
+
+    let mut indexes = Vec::new();
+    for (name, table) in EXPECTED_INDEXES {
+        let exists = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?1 AND tbl_name = ?2)",
+            [name, table],
+            |row| row.get(0),
+        )?;
+        indexes.push(IndexStatus {
+            name: name.to_string(),
+            table: table.to_string(),
+            exists,
+        });
+    }
+
+    Ok(DbPerformanceReport {
+        history_rows,
+        queries: timings,
+        indexes,
+    })
+}
+
+// ============ Backup Operations ============
+
+/// Tables a database must have to be restored
+const REQUIRED_TABLES: &[&str] = &["commands_history", "ai_suggestions", "workflows", "preferences"];
+
+/// Write a consistent copy of the database to a new file
+pub fn backup_database(dest: &Path) -> Result<()> {
+    if dest.exists() {
+        anyhow::bail!("{} already exists", dest.display());
+    }
+    // Holding the lock keeps writes out while VACUUM INTO snapshots the database
+    let conn = get_db().lock();
+    conn.execute("VACUUM INTO ?1", [dest.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Replace the live database with a backup, after checking the backup is sound
+pub fn restore_database(src: &Path) -> Result<()> {
+    let db_path = get_db_path();
+    // Staged beside the live file so the final rename stays on one filesystem
+    let staged = db_path.with_extension("db.restore");
+    std::fs::copy(src, &staged)?;
+
+    // Holding the lock waits out any write in progress and blocks new ones until the swap is done
+    let mut conn = get_db().lock();
+    swap_database(&mut conn, &staged, &db_path)
+}
+
+/// Replace the database at `db_path` with the file at `staged`, reopening `live` on the result.
+/// The original is kept until the restored copy opens, and put back if anything fails.
+fn swap_database(live: &mut Connection, staged: &Path, db_path: &Path) -> Result<()> {
+    let previous = db_path.with_extension("db.bak");
+
+    // Check the copy and migrate it before the live file is touched
+    if let Err(e) = validate_backup(staged).and_then(|_| open_database(staged).map(drop)) {
+        let _ = std::fs::remove_file(staged);
+        return Err(e);
+    }
+
+    // The live file has to be closed before it can be renamed on Windows
+    let open = std::mem::replace(live, Connection::open_in_memory()?);
+    if let Err((open, e)) = open.close() {
+        *live = open;
+        let _ = std::fs::remove_file(staged);
+        return Err(e.into());
+    }
+
+    let _ = std::fs::remove_file(&previous);
+    let restored = std::fs::rename(db_path, &previous)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Ok(std::fs::rename(staged, db_path)?))
+        .and_then(|_| open_database(db_path));
+
+    match restored {
+        Ok(conn) => {
+            *live = conn;
+            let _ = std::fs::remove_file(&previous);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(staged);
+            // Put the original back, or run from the kept copy if even that fails
+            let original = if !previous.exists() || std::fs::rename(&previous, db_path).is_ok() {
+                db_path.to_path_buf()
+            } else {
+                previous
+            };
+            match open_database(&original) {
+                Ok(conn) => {
+                    *live = conn;
+                    Err(e)
+                }
+                Err(reopen_err) => Err(e.context(format!(
+                    "the original database at {} could not be reopened: {}",
+                    original.display(),
+                    reopen_err
+                ))),
+            }
+        }
+    }
+}
+
+/// Check that a file is an intact database with this app's tables and a schema version it understands
+fn validate_backup(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        anyhow::bail!("Backup failed the integrity check: {}", integrity);
+    }
+
+    for table in REQUIRED_TABLES {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            [table],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            anyhow::bail!("Backup is missing the {} table", table);
+        }
+    }
+
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > migrations().len() {
+        anyhow::bail!(
+            "Backup schema version {} is newer than this app supports ({})",
+            version,
+            migrations().len()
+        );
+    }
+    Ok(())
+}
+
 // ============ Preferences Operations ============
 
 /// Get a preference value
@@ -261,6 +1052,63 @@ pub fn set_preference(key: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
+/// Parse a preference, treating an unset, unreadable, or malformed value as missing
+fn get_preference_parsed<T: std::str::FromStr>(key: &str) -> Option<T> {
+    get_preference(key)
+        .ok()
+        .flatten()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Get a "true"/"false" preference, or `default` if unset or malformed
+pub fn get_preference_bool(key: &str, default: bool) -> bool {
+    get_preference_parsed(key).unwrap_or(default)
+}
+
+/// Get an integer preference, or `default` if unset or malformed
+pub fn get_preference_i64(key: &str, default: i64) -> i64 {
+    get_preference_parsed(key).unwrap_or(default)
+}
+
+/// Get a floating-point preference, or `default` if unset or malformed
+pub fn get_preference_f64(key: &str, default: f64) -> f64 {
+    get_preference_parsed::<f64>(key)
+        .filter(|value| value.is_finite())
+        .unwrap_or(default)
+}
+
+/// Store a boolean preference as "true" or "false"
+pub fn set_preference_bool(key: &str, value: bool) -> Result<()> {
+    set_preference(key, &value.to_string())
+}
+
+/// Store an integer preference
+pub fn set_preference_i64(key: &str, value: i64) -> Result<()> {
+    set_preference(key, &value.to_string())
+}
+
+/// Store a floating-point preference, rejecting NaN and infinities
+pub fn set_preference_f64(key: &str, value: f64) -> Result<()> {
+    if !value.is_finite() {
+        anyhow::bail!("Preference '{}' must be a finite number", key);
+    }
+    set_preference(key, &value.to_string())
+}
+
+/// Set several preferences at once, all or nothing
+pub fn set_preferences(values: &[(&str, &str)]) -> Result<()> {
+    let mut conn = get_db().lock();
+    let tx = conn.transaction()?;
+    for (key, value) in values {
+        tx.execute(
+            "INSERT OR REPLACE INTO preferences (key, value) VALUES (?1, ?2)",
+            (key, value),
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
 /// Get all preferences
 pub fn get_all_preferences() -> Result<Vec<Preference>> {
     let conn = get_db().lock();
@@ -281,4 +1129,154 @@ pub fn get_all_preferences() -> Result<Vec<Preference>> {
     Ok(prefs)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_migrations() {
+        // A database from before the sensitive and fingerprint columns existed
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE commands_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                command_text TEXT NOT NULL
+            );
+            INSERT INTO commands_history (timestamp, command_text) VALUES ('t', 'ls /tmp');",
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, migrations().len());
+        let fingerprint: String = conn
+            .query_row("SELECT fingerprint FROM commands_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(fingerprint, "ls <path>");
+
+        // Already up to date: nothing runs again
+        run_migrations(&conn).unwrap();
 
+        conn.execute_batch("PRAGMA user_version = 99").unwrap();
+        assert!(run_migrations(&conn).is_err());
+    }
+
+    #[test]
+    fn test_query_history_for_project() {
+        let dir = std::env::temp_dir().join(format!("neural-project-history-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("app").join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("app-other")).unwrap();
+        let app = context::normalize_path(&dir.join("app"));
+
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        run_migrations(&conn).unwrap();
+        for (n, cwd) in [
+            app.clone(),
+            context::normalize_path(&dir.join("app").join("src")),
+            // Differs only in case; never created, so it's stored as given
+            dir.join("App").to_string_lossy().to_string(),
+            context::normalize_path(&dir.join("app-other")),
+        ]
+        .iter()
+        .enumerate()
+        {
+            conn.execute(
+                "INSERT INTO commands_history (timestamp, command_text, cwd) VALUES (?1, 'ls', ?2)",
+                (n.to_string(), cwd),
+            )
+            .unwrap();
+        }
+
+        let cwds = |root: &Path| -> Vec<String> {
+            query_history_for_project(&conn, &root.to_string_lossy(), 10, 0)
+                .unwrap()
+                .into_iter()
+                .filter_map(|entry| entry.cwd)
+                .collect()
+        };
+
+        // A non-canonical root matches the same rows, and siblings never do
+        let expected = vec![context::normalize_path(&dir.join("app").join("src")), app.clone()];
+        assert_eq!(cwds(&dir.join("app")), expected);
+        assert_eq!(cwds(&dir.join("app").join("src").join("..")), expected);
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link");
+            let _ = std::fs::remove_file(&link);
+            std::os::unix::fs::symlink(dir.join("app"), &link).unwrap();
+            assert_eq!(cwds(&link), expected);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validate_backup() {
+        let dir = std::env::temp_dir().join(format!("neural-backup-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let valid = dir.join("valid.db");
+        let _ = std::fs::remove_file(&valid);
+        let conn = Connection::open(&valid).unwrap();
+        create_tables(&conn).unwrap();
+        run_migrations(&conn).unwrap();
+        drop(conn);
+        assert!(validate_backup(&valid).is_ok());
+
+        let not_db = dir.join("notes.txt");
+        std::fs::write(&not_db, "not a database").unwrap();
+        assert!(validate_backup(&not_db).is_err());
+
+        let foreign = dir.join("foreign.db");
+        let _ = std::fs::remove_file(&foreign);
+        Connection::open(&foreign)
+            .unwrap()
+            .execute_batch("CREATE TABLE other (id INTEGER)")
+            .unwrap();
+        assert_eq!(
+            validate_backup(&foreign).unwrap_err().to_string(),
+            "Backup is missing the commands_history table"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_swap_database() {
+        let dir = std::env::temp_dir().join(format!("neural-swap-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("neural.db");
+        let staged = dir.join("neural.db.restore");
+        let prefs = |conn: &Connection| -> String {
+            conn.query_row("SELECT value FROM preferences WHERE key = 'k'", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let mut live = open_database(&db_path).unwrap();
+        live.execute_batch("INSERT INTO preferences (key, value) VALUES ('k', 'original')")
+            .unwrap();
+
+        // A file that isn't a database is rejected and the live one keeps working
+        std::fs::write(&staged, "not a database").unwrap();
+        assert!(swap_database(&mut live, &staged, &db_path).is_err());
+        assert_eq!(prefs(&live), "original");
+        assert!(!staged.exists());
+
+        // A valid backup replaces the live database and the kept original is cleaned up
+        let backup = open_database(&staged).unwrap();
+        backup
+            .execute_batch("INSERT INTO preferences (key, value) VALUES ('k', 'restored')")
+            .unwrap();
+        drop(backup);
+        swap_database(&mut live, &staged, &db_path).unwrap();
+        assert_eq!(prefs(&live), "restored");
+        assert!(!dir.join("neural.db.bak").exists());
+
+        drop(live);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
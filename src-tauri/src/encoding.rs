@@ -0,0 +1,179 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, Encoding, UTF_16LE, UTF_8};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// CSI sequences (colors, cursor movement), OSC sequences (titles, links) and two-byte escapes
+static ANSI_ESCAPE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-Z\\-_]").unwrap()
+});
+
+/// Remove ANSI escape sequences such as colors from a line of output
+pub fn strip_ansi_codes(text: &str) -> String {
+    ANSI_ESCAPE.replace_all(text, "").to_string()
+}
+
+/// Detect the encoding of a sample of command output
+pub fn detect_encoding(sample: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return Some(encoding);
+    }
+
+    if is_utf16(sample) {
+        return Some(UTF_16LE);
+    }
+
+    // Valid UTF-8, allowing a multi-byte character cut off at the end of the sample
+    match std::str::from_utf8(sample) {
+        Ok(_) => return Some(UTF_8),
+        Err(e) if e.error_len().is_none() => return Some(UTF_8),
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, true);
+    let (encoding, confident) = detector.guess_assess(None, true);
+    confident.then_some(encoding)
+}
+
+/// Check if output looks like UTF-16 (a BOM, or mostly ASCII with zero high bytes)
+pub fn is_utf16(sample: &[u8]) -> bool {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding != UTF_8;
+    }
+
+    let sample = &sample[..sample.len().min(1024)];
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return false;
+    }
+
+    let zero_high = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let zero_low = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+
+    zero_high * 10 >= pairs * 8 && zero_low * 10 < pairs
+}
+
+/// Incrementally decodes a command's output stream into lines
+pub struct StreamDecoder {
+    decoder: Option<Decoder>,
+    pending: String,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self {
+            decoder: None,
+            pending: String::new(),
+        }
+    }
+
+    /// Decode a chunk of output, returning any lines it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        // ASCII reads the same in every supported encoding, so the encoding is only
+        // chosen once non-ASCII output (or UTF-16, which is full of zero bytes) shows up
+        if self.decoder.is_none() && chunk.is_ascii() && !is_utf16(chunk) {
+            self.pending.extend(chunk.iter().map(|&byte| char::from(byte)));
+        } else {
+            let decoder = self.decoder.get_or_insert_with(|| {
+                detect_encoding(chunk).unwrap_or(UTF_8).new_decoder()
+            });
+
+            let capacity = decoder
+                .max_utf8_buffer_length(chunk.len())
+                .unwrap_or(chunk.len() * 3);
+            let mut decoded = String::with_capacity(capacity);
+            let _ = decoder.decode_to_string(chunk, &mut decoded, false);
+            self.pending.push_str(&decoded);
+        }
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        lines
+    }
+
+    /// Flush the trailing partial line at the end of the stream
+    pub fn finish(&mut self) -> Option<String> {
+        if let Some(decoder) = self.decoder.as_mut() {
+            let mut decoded = String::with_capacity(16);
+            let _ = decoder.decode_to_string(&[], &mut decoded, true);
+            self.pending.push_str(&decoded);
+        }
+
+        if self.pending.is_empty() {
+            None
+        } else {
+            let line = std::mem::take(&mut self.pending);
+            Some(line.trim_end_matches('\r').to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode a whole stream in one chunk
+    fn decode_output(data: &[u8]) -> String {
+        let mut decoder = StreamDecoder::new();
+        let mut lines = decoder.push(data);
+        lines.extend(decoder.finish());
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_decode_utf16_with_bom() {
+        let mut data = vec![0xFF, 0xFE];
+        for unit in "héllo".encode_utf16() {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(decode_output(&data), "héllo");
+    }
+
+    #[test]
+    fn test_decode_utf16_without_bom() {
+        let data: Vec<u8> = "Directory listing"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert!(is_utf16(&data));
+        assert_eq!(decode_output(&data), "Directory listing");
+    }
+
+    #[test]
+    fn test_strip_ansi_codes() {
+        assert_eq!(
+            strip_ansi_codes("\x1b[1m\x1b[32m   Compiling\x1b[0m neural v0.1.0"),
+            "   Compiling neural v0.1.0"
+        );
+        assert_eq!(strip_ansi_codes("\x1b]0;title\x07done\x1b[2K"), "done");
+        assert_eq!(strip_ansi_codes("plain [text]"), "plain [text]");
+    }
+
+    #[test]
+    fn test_stream_decoder_lines() {
+        let mut decoder = StreamDecoder::new();
+        assert_eq!(decoder.push(b"first\r\nsec"), vec!["first"]);
+        assert_eq!(decoder.push(b"ond\nthird"), vec!["second"]);
+        assert_eq!(decoder.finish(), Some("third".to_string()));
+    }
+
+    #[test]
+    fn test_stream_decoder_ascii_then_code_page() {
+        // A console tool's ASCII header, then Windows-1252 text
+        let mut decoder = StreamDecoder::new();
+        assert_eq!(
+            decoder.push(b"Volume in drive C has no label.\r\n"),
+            vec!["Volume in drive C has no label."]
+        );
+        let (latin, _, _) = encoding_rs::WINDOWS_1252
+            .encode("Größe der Dateien für München, Köln und Düsseldorf: überprüft\r\n");
+        assert_eq!(
+            decoder.push(&latin),
+            vec!["Größe der Dateien für München, Köln und Düsseldorf: überprüft"]
+        );
+    }
+}
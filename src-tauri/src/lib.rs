@@ -2,9 +2,12 @@ mod ai;
 mod commands;
 mod context;
 mod db;
+mod encoding;
 mod models;
 mod redaction;
 mod runner;
+mod shell;
+mod templates;
 mod workflow;
 
 use commands::*;
@@ -20,6 +23,12 @@ pub fn run() {
         .setup(|_app| {
             // Initialize database
             db::init_db().expect("Failed to initialize database");
+            if let Ok(Some(patterns)) = db::get_preference("custom_redaction_patterns") {
+                redaction::load_custom_patterns(&patterns);
+            }
+            if let Ok(Some(allowlist)) = db::get_preference("redaction_allowlist") {
+                redaction::load_allowlist(&allowlist);
+            }
             tracing::info!("Project Neural initialized successfully");
             Ok(())
         })
@@ -28,36 +37,97 @@ pub fn run() {
             nl_to_cmd,
             // Command Execution
             run_command,
+            run_command_blocking,
             kill_command,
+            kill_all_commands,
+            send_stdin,
+            close_stdin,
+            quote_for_shell,
+            join_as_pipeline,
+            abort_all,
             get_running_commands,
+            reap_finished_commands,
             // Context
             get_context,
             find_project_root,
+            get_recent_projects,
             // AI Features
             analyze_error,
+            explain_error,
             explain_command,
+            compare_commands,
             is_ai_configured,
+            get_ai_error_kind,
             set_api_key,
             set_gemini_api_key,
             set_openai_api_key,
             set_ai_provider,
             set_ai_model,
+            set_ai_provider_and_model,
+            get_ai_config,
+            list_available_models,
+            get_model_migrations,
+            get_effective_endpoints,
+            get_model_capabilities,
+            get_model_defaults,
+            export_ai_log,
             clear_api_key,
             // Workflows
             run_workflow,
             create_workflow,
+            update_workflow,
+            delete_workflow,
+            export_workflow,
+            import_workflow,
             get_workflows,
+            get_workflow_definition_raw,
+            validate_workflow_dependencies,
+            recheck_workflow_safety,
+            workflow_signature,
             generate_workflow,
+            regenerate_workflow_step,
+            // Command Templates
+            save_template,
+            get_templates,
+            delete_template,
+            render_template,
             // History & Preferences
             get_history,
+            get_history_for_project,
+            search_command_history,
+            get_command_output,
+            get_top_commands,
+            get_history_stats,
+            mark_command_sensitive,
+            delete_command_history,
+            clear_command_history,
+            audit_history_validity,
             get_suggestions_for_command,
+            get_suggestions_grouped,
+            get_blocked_commands,
+            find_orphaned_records,
+            cleanup_orphaned_records,
+            diagnose_db_performance,
+            backup_database,
+            restore_database,
             get_preference,
             set_preference,
+            get_preference_bool,
+            get_preference_i64,
+            get_preference_f64,
+            set_preference_bool,
+            set_preference_i64,
+            set_preference_f64,
             get_all_preferences,
             // Security
             validate_command,
+            validate_pipeline,
+            check_overwrites,
             is_interactive_command,
             redact_sensitive,
+            detect_secrets,
+            validate_redaction_pattern,
+            locate_sensitive,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
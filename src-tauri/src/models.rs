@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 /// Command history entry stored in SQLite
@@ -11,6 +13,9 @@ pub struct CommandHistory {
     pub exit_code: Option<i32>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    /// Never included in AI prompts as history context
+    #[serde(default)]
+    pub sensitive: bool,
 }
 
 /// AI suggestion entry
@@ -36,6 +41,16 @@ pub struct Workflow {
     pub last_run_at: Option<String>,
 }
 
+/// Portable workflow file for sharing, without database-specific fields
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowExport {
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub steps: serde_json::Value,
+}
+
 /// Single workflow step
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkflowStep {
@@ -44,6 +59,53 @@ pub struct WorkflowStep {
     pub cwd: Option<String>,
     #[serde(default)]
     pub continue_on_fail: bool,
+    /// Shell to run this step in, overriding the default
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Step numbers that must finish before this one
+    #[serde(default)]
+    pub depends_on: Vec<i32>,
+    /// Runs after the other steps, even if one failed or the workflow timed out.
+    /// Cleanup steps only accept a run_if of "always"
+    #[serde(default)]
+    pub cleanup: bool,
+    /// Extra environment variables for this step
+    #[serde(default)]
+    pub env: Option<HashMap<String, String>>,
+    /// Start from an empty environment instead of inheriting the app's
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Steps sharing a group run at the same time, starting where the group's first step is
+    #[serde(default)]
+    pub parallel_group: Option<u32>,
+    /// When to run: "on_success" (default), "on_failure" or "always"
+    #[serde(default)]
+    pub run_if: Option<String>,
+    /// Store the step's trimmed stdout under this name for later `${name}` references
+    #[serde(default)]
+    pub capture_var: Option<String>,
+    /// Kill the step's command after this many seconds and fail the step
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Re-run the step this many times if it exits non-zero
+    #[serde(default)]
+    pub retries: Option<u32>,
+    /// Delay before the first retry, doubling for each retry after it
+    #[serde(default)]
+    pub retry_delay_secs: Option<u64>,
+}
+
+/// Named command with `{placeholder}` parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandTemplate {
+    pub id: Option<i64>,
+    pub name: String,
+    pub template: String,
+    pub description: Option<String>,
+    /// Placeholder names found in the template
+    #[serde(default)]
+    pub placeholders: Vec<String>,
+    pub created_at: Option<String>,
 }
 
 /// User preference entry
@@ -63,7 +125,15 @@ pub struct Context {
     pub has_requirements_txt: bool,
     pub has_manage_py: bool,
     pub has_composer_json: bool,
+    pub has_go_mod: bool,
+    pub has_pom_xml: bool,
+    pub has_build_gradle: bool,
+    pub has_gemfile: bool,
+    /// A .csproj or .sln file is present
+    pub has_dotnet_project: bool,
     pub has_git: bool,
+    /// More than one JS package manager lockfile is present
+    pub lockfile_conflict: bool,
     pub npm_scripts: Option<Vec<String>>,
     pub cwd: String,
 }
@@ -77,7 +147,13 @@ impl Default for Context {
             has_requirements_txt: false,
             has_manage_py: false,
             has_composer_json: false,
+            has_go_mod: false,
+            has_pom_xml: false,
+            has_build_gradle: false,
+            has_gemfile: false,
+            has_dotnet_project: false,
             has_git: false,
+            lockfile_conflict: false,
             npm_scripts: None,
             cwd: String::new(),
         }
@@ -88,8 +164,23 @@ impl Default for Context {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiCommandResponse {
     pub commands: Vec<String>,
+    /// Other valid command sets the user can pick instead
+    #[serde(default)]
+    pub alternatives: Vec<Vec<String>>,
     pub warning: Option<String>,
     pub explanation: Option<String>,
+    /// Model's confidence in the commands, from 0.0 to 1.0
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    /// Model's risk rating: "low", "medium" or "high"
+    #[serde(default)]
+    pub risk_level: Option<String>,
+    /// Provider that served the response, which differs from the selected one after a fallback
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// The commands are stages of one pipeline, to be joined with `|` rather than run in turn
+    #[serde(default)]
+    pub pipeline: bool,
 }
 
 /// AI error analysis response
@@ -98,6 +189,9 @@ pub struct AiErrorAnalysis {
     pub explanation: String,
     pub fixes: Vec<String>,
     pub confidence: Option<f32>,
+    /// Provider that served the response
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 /// AI command explanation response
@@ -105,6 +199,47 @@ pub struct AiErrorAnalysis {
 pub struct AiExplanation {
     pub summary: String,
     pub parts: Vec<CommandPart>,
+    /// Provider that served the response
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// The response was cut off and only its complete parts were kept
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// The selected AI provider and model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiConfig {
+    pub provider: String,
+    pub model: String,
+    /// Whether the provider has the key it needs
+    pub configured: bool,
+}
+
+/// Features supported by an AI model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    pub json_mode: bool,
+    pub streaming: bool,
+    pub max_tokens: u32,
+}
+
+/// Suggested generation settings for a model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDefaults {
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub context_window: usize,
+}
+
+/// AI comparison of two commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandComparison {
+    pub differences: Vec<String>,
+    pub recommendation: String,
+    /// Provider that served the response
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -113,12 +248,124 @@ pub struct CommandPart {
     pub explain: String,
 }
 
+/// Command explanation in the requested format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExplanationOutput {
+    Structured(AiExplanation),
+    Prose(String),
+}
+
+/// Resolved AI endpoint for diagnostics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiEndpoints {
+    pub provider: String,
+    pub chat_url: String,
+    /// Where the model list is fetched from
+    pub models_url: String,
+}
+
+/// A directory commands were recently run in, with its detected stack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentProject {
+    pub cwd: String,
+    pub project_type: Option<String>,
+    pub last_used: String,
+}
+
+/// A frequently run command, grouping runs that differ only in paths or numbers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopCommand {
+    pub fingerprint: String,
+    /// Most recent command text with this fingerprint
+    pub latest_command: String,
+    pub count: i64,
+    pub last_used: String,
+}
+
+/// Aggregate counts over the whole command history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub total: i64,
+    /// Finished commands with a non-zero exit code
+    pub failures: i64,
+    pub ai_generated: i64,
+    pub top_commands: Vec<CommandCount>,
+}
+
+/// How many times an exact command text was run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandCount {
+    pub command_text: String,
+    pub count: i64,
+}
+
 /// Command handle returned when starting a command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandHandle {
     pub id: i64,
     pub command_text: String,
     pub timestamp: String,
+    /// Soft warning that doesn't block execution (e.g. network access)
+    pub warning: Option<String>,
+}
+
+/// Final result of a command run to completion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Whether a history entry can still be re-run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryValidity {
+    pub id: i64,
+    pub cwd_exists: bool,
+    pub program_found: bool,
+    pub missing_program: Option<String>,
+}
+
+/// Database rows left behind by deleted history or crashed runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanReport {
+    /// Suggestions whose command_history_id no longer exists
+    pub dangling_suggestions: Vec<i64>,
+    /// History rows that never recorded an exit code
+    pub stale_history: Vec<i64>,
+}
+
+/// How long a representative database query took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryTiming {
+    pub name: String,
+    pub duration_ms: f64,
+    pub rows: usize,
+}
+
+/// Whether an index the app relies on is present
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexStatus {
+    pub name: String,
+    pub table: String,
+    pub exists: bool,
+}
+
+/// Query timings and index status for diagnosing a slow database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbPerformanceReport {
+    pub history_rows: i64,
+    pub queries: Vec<QueryTiming>,
+    pub indexes: Vec<IndexStatus>,
+}
+
+/// Summary of everything stopped by abort_all
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbortSummary {
+    pub commands_killed: Vec<i64>,
+    pub ai_requests_cancelled: usize,
+    pub workflows_cancelled: usize,
 }
 
 /// Danger warning for risky commands
@@ -129,6 +376,59 @@ pub struct DangerWarning {
     pub severity: String, // "high", "medium", "low"
 }
 
+/// Existing files a command would overwrite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverwriteCheck {
+    pub would_overwrite: Vec<String>,
+}
+
+/// Command rejected by the safety checks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedCommand {
+    pub id: Option<i64>,
+    pub timestamp: String,
+    pub command: String,
+    pub reason: String,
+    pub severity: String,
+}
+
+/// Danger warning for a single workflow step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepWarning {
+    pub step: i32,
+    pub warning: DangerWarning,
+}
+
+/// Danger scan result for one segment of a compound command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentWarning {
+    pub segment: String,
+    pub warning: Option<DangerWarning>,
+}
+
+/// A kind of secret found in text and how many times it appears
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensitiveItem {
+    pub category: String,
+    pub count: usize,
+}
+
+/// A user-defined redaction regex from the custom_redaction_patterns preference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionPattern {
+    pub pattern: String,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Byte range of a secret found in text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensitiveSpan {
+    pub label: String,
+    pub start: usize,
+    pub end: usize,
+}
+
 /// Workflow run result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkflowRunResult {
@@ -138,6 +438,12 @@ pub struct WorkflowRunResult {
     pub failed_step: Option<i32>,
     pub error: Option<String>,
     pub suggestion: Option<AiErrorAnalysis>,
+    /// The run was stopped for exceeding its total timeout
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Steps were only listed, not executed
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 
@@ -1,28 +1,143 @@
 use std::collections::HashMap;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use chrono::Utc;
 use parking_lot::Mutex;
+use regex::Regex;
 use tauri::{AppHandle, Emitter};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::{Child, Command};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin};
+use tokio::time::{Duration, Instant};
 
 use crate::db;
+use crate::encoding::{self, StreamDecoder};
 use crate::models::{CommandHandle, CommandHistory};
 use crate::redaction::is_binary_output;
+use crate::shell;
+
+/// Exit code recorded when a command exceeds its timeout
+pub const TIMEOUT_EXIT_CODE: i32 = -2;
+
+/// Exit code recorded when a command's process could not be started
+pub const SPAWN_FAILED_EXIT_CODE: i32 = -3;
+
+/// Exit code recorded when a command was killed by the user
+pub const KILLED_EXIT_CODE: i32 = -4;
+
+/// A tracked child process and, if input was enabled, its stdin
+struct RunningProcess {
+    child: Child,
+    stdin: Option<Arc<tokio::sync::Mutex<ChildStdin>>>,
+}
 
 /// Store for active running processes
-static RUNNING_PROCESSES: once_cell::sync::Lazy<Arc<Mutex<HashMap<i64, Child>>>> =
+static RUNNING_PROCESSES: once_cell::sync::Lazy<Arc<Mutex<HashMap<i64, RunningProcess>>>> =
     once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
 
+/// Next id for runs that aren't saved to history
+static SCRATCH_IDS: AtomicI64 = AtomicI64::new(-1);
+
+/// Exit codes for processes removed from tracking outside the exit watcher
+static EXIT_CODES: once_cell::sync::Lazy<Mutex<HashMap<i64, i32>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Known progress lines from package managers and build tools, with their phase
+static PROGRESS_PATTERNS: once_cell::sync::Lazy<Vec<(Regex, &'static str)>> =
+    once_cell::sync::Lazy::new(|| {
+        vec![
+            (Regex::new(r"^\s*Compiling (\S+ v\S+)").unwrap(), "compiling"),
+            (Regex::new(r"^\s*Downloaded (\S+ v\S+)").unwrap(), "downloading"),
+            (Regex::new(r"^\s*Finished (.*)").unwrap(), "finished"),
+            (Regex::new(r"added (\d+) packages?").unwrap(), "installed"),
+            (Regex::new(r"^Collecting (\S+)").unwrap(), "downloading"),
+            (Regex::new(r"^Installing collected packages: (.*)").unwrap(), "installing"),
+            (Regex::new(r"^Successfully installed (.*)").unwrap(), "installed"),
+            (Regex::new(r"^\[\d+/\d+\] (.*)").unwrap(), "step"),
+        ]
+    });
+
+/// Join stdout and stderr into one log, marking where stderr begins
+pub fn combine_output(stdout: &str, stderr: &str) -> String {
+    if stderr.is_empty() {
+        return stdout.to_string();
+    }
+
+    let mut combined = stdout.to_string();
+    if !combined.is_empty() && !combined.ends_with('\n') {
+        combined.push('\n');
+    }
+    combined.push_str("--- stderr ---\n");
+    combined.push_str(stderr);
+    combined
+}
+
+/// Resolve the shell to run in: the per-call override, then the shell preference, then the OS default
+pub fn select_shell(name: Option<&str>) -> Result<&'static shell::ShellSpec, String> {
+    match name.filter(|n| !n.trim().is_empty()) {
+        Some(name) => shell::resolve_shell(Some(name)),
+        None => {
+            let preferred = db::get_preference("shell").ok().flatten();
+            shell::resolve_shell(preferred.as_deref())
+        }
+    }
+}
+
+/// Describe how a command would be run without spawning it
+pub fn dry_run_command(
+    app: AppHandle,
+    command: String,
+    cwd: Option<String>,
+    shell: Option<&str>,
+) -> Result<CommandHandle, String> {
+    let timestamp = Utc::now().to_rfc3339();
+    let working_dir = cwd.unwrap_or_else(|| ".".to_string());
+    let spec = select_shell(shell)?;
+    let id = SCRATCH_IDS.fetch_sub(1, Ordering::SeqCst);
+
+    let mut argv = vec![spec.program.to_string()];
+    argv.extend(spec.args.iter().map(|a| a.to_string()));
+    argv.push(command.clone());
+
+    let _ = app.emit(
+        "command_dry_run",
+        serde_json::json!({
+            "id": id,
+            "command_text": command,
+            "shell": spec.name,
+            "argv": argv,
+            "cwd": working_dir,
+            "timestamp": timestamp
+        }),
+    );
+
+    Ok(CommandHandle {
+        id,
+        command_text: command,
+        timestamp,
+        warning: None,
+    })
+}
+
 /// Run a command and stream output via events
+#[allow(clippy::too_many_arguments)]
 pub async fn run_command_emit(
     app: AppHandle,
     command: String,
     cwd: Option<String>,
     generated_by_ai: bool,
+    save_history: bool,
+    merge_output: bool,
+    timeout_secs: Option<u64>,
+    env: Option<HashMap<String, String>>,
+    clear_env: bool,
+    shell: Option<&str>,
+    interactive_input: bool,
+    path_override: Option<&str>,
 ) -> Result<CommandHandle, String> {
+    // Fail before recording history if the shell isn't available
+    let shell = select_shell(shell)?;
     let timestamp = Utc::now().to_rfc3339();
     let working_dir = cwd.clone().unwrap_or_else(|| ".".to_string());
 
@@ -36,9 +151,15 @@ pub async fn run_command_emit(
         exit_code: None,
         stdout: None,
         stderr: None,
+        sensitive: false,
     };
 
-    let id = db::insert_command_history(&history).map_err(|e| e.to_string())?;
+    // Scratch runs get a temporary negative id and are never persisted
+    let id = if save_history {
+        db::insert_command_history(&history).map_err(|e| e.to_string())?
+    } else {
+        SCRATCH_IDS.fetch_sub(1, Ordering::SeqCst)
+    };
 
     // Emit start event
     let _ = app.emit(
@@ -55,119 +176,195 @@ pub async fn run_command_emit(
     println!("[DEBUG] About to execute command: '{}'", command);
     println!("[DEBUG] Working directory: '{}'", working_dir);
 
-    // Determine shell based on OS
-    #[cfg(target_os = "windows")]
-    let mut cmd = Command::new("powershell");
-    #[cfg(target_os = "windows")]
-    cmd.args(["-NoProfile", "-NonInteractive", "-Command", &command]);
-    
-    #[cfg(not(target_os = "windows"))]
-    let mut cmd = Command::new("sh");
-    #[cfg(not(target_os = "windows"))]
-    cmd.args(["-c", &command]);
-
-    // Spawn the process
-    let mut child = cmd
+    let mut cmd = shell::build_command(shell, &command);
+    apply_env(&mut cmd, env.as_ref(), clear_env, path_override);
+
+    // A process group of its own lets kill_command stop everything the shell started
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    // Spawn the process, recording a failure to start in the history row
+    let spawned = cmd
         .current_dir(&working_dir)
+        .stdin(if interactive_input { Stdio::piped() } else { Stdio::inherit() })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to spawn command: {}", e))?;
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            let error = format!("Failed to spawn command: {}", e);
+            if save_history {
+                let _ = db::update_command_history_output(
+                    id,
+                    None,
+                    Some(&error),
+                    Some(SPAWN_FAILED_EXIT_CODE),
+                );
+            }
+            let _ = app.emit(
+                "command_exit",
+                serde_json::json!({
+                    "id": id,
+                    "exit_code": SPAWN_FAILED_EXIT_CODE
+                }),
+            );
+            return Err(error);
+        }
+    };
 
     let stdout = child.stdout.take();
     let stderr = child.stderr.take();
 
+    let stdin = child
+        .stdin
+        .take()
+        .map(|stdin| Arc::new(tokio::sync::Mutex::new(stdin)));
+
     // Store the child process for potential cancellation
-    RUNNING_PROCESSES.lock().insert(id, child);
+    RUNNING_PROCESSES.lock().insert(id, RunningProcess { child, stdin });
 
-    let app_stdout = app.clone();
-    let app_stderr = app.clone();
     let app_exit = app.clone();
 
-    let stdout_buffer = Arc::new(Mutex::new(String::new()));
-    let stderr_buffer = Arc::new(Mutex::new(String::new()));
-    let stdout_buf_clone = stdout_buffer.clone();
-    let stderr_buf_clone = stderr_buffer.clone();
+    // Progress hints are on unless the user disabled them
+    let progress_hints = db::get_preference_bool("progress_hints", true);
 
-    // Spawn stdout reader task
-    if let Some(stdout) = stdout {
-        tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+    // Every line is still streamed live, but history only keeps up to the limit
+    let output_limit = max_output_bytes();
+    let strip_ansi = strip_ansi_enabled();
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                stdout_buf_clone.lock().push_str(&line);
-                stdout_buf_clone.lock().push('\n');
+    let sink = Arc::new(OutputSink {
+        app,
+        id,
+        progress_hints,
+        merge_output,
+        strip_ansi,
+        seq: AtomicU64::new(0),
+        stdout_buffer: Mutex::new(CappedBuffer::new(output_limit)),
+        stderr_buffer: Mutex::new(CappedBuffer::new(output_limit)),
+    });
 
-                let _ = app_stdout.emit(
-                    "command_stdout",
-                    serde_json::json!({
-                        "id": id,
-                        "chunk": format!("{}\n", line)
-                    }),
-                );
-            }
-        });
+    if merge_output {
+        // One reader for both pipes keeps lines in the order they arrive
+        if let (Some(stdout), Some(stderr)) = (stdout, stderr) {
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                read_merged_lines(
+                    stdout,
+                    stderr,
+                    |source, line| sink.line(source, line),
+                    |source| sink.binary(source),
+                )
+                .await;
+            });
+        }
+    } else {
+        // Spawn stdout reader task
+        if let Some(stdout) = stdout {
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                read_decoded_lines(
+                    stdout,
+                    |line| sink.line(OutputSource::Stdout, line),
+                    || sink.binary(OutputSource::Stdout),
+                )
+                .await;
+            });
+        }
+
+        // Spawn stderr reader task
+        if let Some(stderr) = stderr {
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                read_decoded_lines(
+                    stderr,
+                    |line| sink.line(OutputSource::Stderr, line),
+                    || sink.binary(OutputSource::Stderr),
+                )
+                .await;
+            });
+        }
     }
 
-    // Spawn stderr reader task
-    if let Some(stderr) = stderr {
-        tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
+    // Kill the command once it passes its timeout or the global runtime ceiling
+    let max_runtime = max_runtime_secs();
+    let timeout = resolve_timeout(timeout_secs);
+    let deadline = timeout.map(|secs| Instant::now() + Duration::from_secs(secs));
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                stderr_buf_clone.lock().push_str(&line);
-                stderr_buf_clone.lock().push('\n');
+    // Spawn exit watcher task
+    tokio::spawn(async move {
+        // Poll the process while it stays in the map, so it can still be killed
+        let exit_code = loop {
+            let mut timed_out = None;
+            let finished = {
+                let mut processes = RUNNING_PROCESSES.lock();
+                match processes.get_mut(&id) {
+                    Some(RunningProcess { child, .. }) => match child.try_wait() {
+                        Ok(Some(status)) => {
+                            processes.remove(&id);
+                            Some(status.code().unwrap_or(-1))
+                        }
+                        Ok(None) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                            timed_out = processes.remove(&id);
+                            Some(TIMEOUT_EXIT_CODE)
+                        }
+                        Ok(None) => None,
+                        Err(_) => {
+                            processes.remove(&id);
+                            Some(-1)
+                        }
+                    },
+                    // Killed or reaped elsewhere
+                    None => Some(EXIT_CODES.lock().remove(&id).unwrap_or(-1)),
+                }
+            };
 
-                let _ = app_stderr.emit(
-                    "command_stderr",
-                    serde_json::json!({
-                        "id": id,
-                        "chunk": format!("{}\n", line)
-                    }),
-                );
+            // Killed after releasing the lock, so other commands aren't held up
+            if let Some(mut process) = timed_out {
+                let _ = kill_process_tree(&mut process.child);
             }
-        });
-    }
 
-    // Spawn exit watcher task
-    let stdout_final = stdout_buffer.clone();
-    let stderr_final = stderr_buffer.clone();
+            if let Some(code) = finished {
+                break code;
+            }
 
-    tokio::spawn(async move {
-        // Wait a bit for the process to be stored
-        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-
-        // Try to wait for the process
-        // Remove from map first, then drop the lock before awaiting
-        let mut child_opt = {
-            let mut processes = RUNNING_PROCESSES.lock();
-            processes.remove(&id)
+            tokio::time::sleep(Duration::from_millis(100)).await;
         };
 
-        let exit_code = if let Some(mut child) = child_opt {
-            match child.wait().await {
-                Ok(status) => status.code().unwrap_or(-1),
-                Err(_) => -1,
+        if exit_code == TIMEOUT_EXIT_CODE {
+            if let Some(secs) = timeout {
+                let message = if max_runtime == Some(secs) {
+                    format!("Command killed after exceeding the maximum runtime of {} seconds", secs)
+                } else {
+                    format!("Command timed out after {} seconds", secs)
+                };
+                sink.line(OutputSource::Stderr, message);
+
+                let _ = app_exit.emit(
+                    "command_timeout",
+                    serde_json::json!({
+                        "id": id,
+                        "timeout_secs": secs
+                    }),
+                );
             }
-        } else {
-            -1
-        };
+        }
 
         // Give time for stdout/stderr to finish
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
 
         // Update database with results
-        let stdout_str = stdout_final.lock().clone();
-        let stderr_str = stderr_final.lock().clone();
-
-        let _ = db::update_command_history_output(
-            id,
-            Some(&stdout_str),
-            Some(&stderr_str),
-            Some(exit_code),
-        );
+        let stdout_str = sink.stdout_buffer.lock().contents();
+        let stderr_str = sink.stderr_buffer.lock().contents();
+
+        if save_history {
+            let _ = db::update_command_history_output(
+                id,
+                Some(&stdout_str),
+                Some(&stderr_str),
+                Some(exit_code),
+            );
+        }
 
         // Emit exit event
         let _ = app_exit.emit(
@@ -183,56 +380,554 @@ pub async fn run_command_emit(
         id,
         command_text: command,
         timestamp,
+        warning: None,
     })
 }
 
+/// Which pipe a line of output came from
+#[derive(Debug, Clone, Copy)]
+enum OutputSource {
+    Stdout,
+    Stderr,
+}
+
+impl OutputSource {
+    fn name(self) -> &'static str {
+        match self {
+            OutputSource::Stdout => "stdout",
+            OutputSource::Stderr => "stderr",
+        }
+    }
+}
+
+/// Collects a command's output and forwards each line to the frontend
+struct OutputSink {
+    app: AppHandle,
+    id: i64,
+    progress_hints: bool,
+    merge_output: bool,
+    /// Remove color codes before emitting and storing lines
+    strip_ansi: bool,
+    /// Order of lines across stdout and stderr, shared by both readers
+    seq: AtomicU64,
+    stdout_buffer: Mutex<CappedBuffer>,
+    stderr_buffer: Mutex<CappedBuffer>,
+}
+
+/// Output kept for history, dropping anything past the size limit
+struct CappedBuffer {
+    text: String,
+    limit: Option<usize>,
+    dropped: usize,
+    binary: bool,
+}
+
+impl CappedBuffer {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            text: String::new(),
+            limit,
+            dropped: 0,
+            binary: false,
+        }
+    }
+
+    /// Append a line, keeping as much of it as fits under the limit
+    fn push_line(&mut self, line: &str) {
+        if self.binary {
+            return;
+        }
+
+        let line = format!("{}\n", line);
+        let Some(limit) = self.limit else {
+            self.text.push_str(&line);
+            return;
+        };
+
+        let kept = floor_char_boundary(&line, limit.saturating_sub(self.text.len()));
+        self.text.push_str(&line[..kept]);
+        self.dropped += line.len() - kept;
+    }
+
+    /// Replace the stream's output with a placeholder
+    fn mark_binary(&mut self) {
+        self.binary = true;
+        self.text = "[Binary output]".to_string();
+        self.dropped = 0;
+    }
+
+    /// The kept output, with a marker if anything was dropped
+    fn contents(&self) -> String {
+        if self.dropped == 0 {
+            self.text.clone()
+        } else {
+            format!("{}...[truncated {} bytes]", self.text, self.dropped)
+        }
+    }
+}
+
+impl OutputSink {
+    /// Buffer a line and emit it as a separate or merged output event
+    fn line(&self, source: OutputSource, line: String) {
+        let line = if self.strip_ansi {
+            encoding::strip_ansi_codes(&line)
+        } else {
+            line
+        };
+
+        let buffer = match source {
+            OutputSource::Stdout => &self.stdout_buffer,
+            OutputSource::Stderr => &self.stderr_buffer,
+        };
+        buffer.lock().push_line(&line);
+
+        let chunk = format!("{}\n", line);
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.merge_output {
+            let _ = self.app.emit(
+                "command_output",
+                serde_json::json!({
+                    "id": self.id,
+                    "seq": seq,
+                    "source": source.name(),
+                    "chunk": chunk
+                }),
+            );
+        } else {
+            // seq lets the frontend rebuild the true interleaving of the two events
+            let _ = self.app.emit(
+                &format!("command_{}", source.name()),
+                serde_json::json!({
+                    "id": self.id,
+                    "seq": seq,
+                    "stream": source.name(),
+                    "chunk": chunk
+                }),
+            );
+        }
+
+        if self.progress_hints {
+            emit_progress_hint(&self.app, self.id, &line);
+        }
+    }
+
+    /// Record a stream as binary and tell the frontend once, instead of emitting garbage lines
+    fn binary(&self, source: OutputSource) {
+        let buffer = match source {
+            OutputSource::Stdout => &self.stdout_buffer,
+            OutputSource::Stderr => &self.stderr_buffer,
+        };
+        buffer.lock().mark_binary();
+
+        let _ = self.app.emit(
+            "command_binary_output",
+            serde_json::json!({
+                "id": self.id,
+                "stream": source.name()
+            }),
+        );
+    }
+}
+
+/// Decodes one output stream, checking its first chunk for binary data
+struct LineStream {
+    decoder: StreamDecoder,
+    sampled: bool,
+    binary: bool,
+}
+
+/// What a chunk of stream data decoded to
+enum Decoded {
+    Lines(Vec<String>),
+    /// The stream turned out to be binary; later chunks are discarded
+    Binary,
+}
+
+impl LineStream {
+    fn new() -> Self {
+        Self {
+            decoder: StreamDecoder::new(),
+            sampled: false,
+            binary: false,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Decoded {
+        if self.binary {
+            return Decoded::Lines(Vec::new());
+        }
+        if !self.sampled {
+            self.sampled = true;
+            if looks_binary(data) {
+                self.binary = true;
+                return Decoded::Binary;
+            }
+        }
+        Decoded::Lines(self.decoder.push(data))
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        if self.binary {
+            None
+        } else {
+            self.decoder.finish()
+        }
+    }
+}
+
+/// Read stdout and stderr in one loop, passing on decoded lines in arrival order
+async fn read_merged_lines<O, E>(
+    mut stdout: O,
+    mut stderr: E,
+    mut on_line: impl FnMut(OutputSource, String),
+    mut on_binary: impl FnMut(OutputSource),
+) where
+    O: AsyncRead + Unpin,
+    E: AsyncRead + Unpin,
+{
+    let mut stdout_decoder = LineStream::new();
+    let mut stderr_decoder = LineStream::new();
+    let mut stdout_buf = [0u8; 8192];
+    let mut stderr_buf = [0u8; 8192];
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+
+    while stdout_open || stderr_open {
+        tokio::select! {
+            read = stdout.read(&mut stdout_buf), if stdout_open => match read {
+                Ok(0) | Err(_) => stdout_open = false,
+                Ok(n) => match stdout_decoder.push(&stdout_buf[..n]) {
+                    Decoded::Lines(lines) => {
+                        for line in lines {
+                            on_line(OutputSource::Stdout, line);
+                        }
+                    }
+                    Decoded::Binary => on_binary(OutputSource::Stdout),
+                },
+            },
+            read = stderr.read(&mut stderr_buf), if stderr_open => match read {
+                Ok(0) | Err(_) => stderr_open = false,
+                Ok(n) => match stderr_decoder.push(&stderr_buf[..n]) {
+                    Decoded::Lines(lines) => {
+                        for line in lines {
+                            on_line(OutputSource::Stderr, line);
+                        }
+                    }
+                    Decoded::Binary => on_binary(OutputSource::Stderr),
+                },
+            },
+        }
+    }
+
+    if let Some(line) = stdout_decoder.finish() {
+        on_line(OutputSource::Stdout, line);
+    }
+    if let Some(line) = stderr_decoder.finish() {
+        on_line(OutputSource::Stderr, line);
+    }
+}
+
+/// Read a process stream, detecting its encoding and passing each decoded line on
+async fn read_decoded_lines<R: AsyncRead + Unpin>(
+    mut reader: R,
+    mut on_line: impl FnMut(String),
+    mut on_binary: impl FnMut(),
+) {
+    let mut decoder = LineStream::new();
+    let mut buf = [0u8; 8192];
+
+    // Binary streams are still drained so the child never blocks on a full pipe
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        match decoder.push(&buf[..n]) {
+            Decoded::Lines(lines) => lines.into_iter().for_each(&mut on_line),
+            Decoded::Binary => on_binary(),
+        }
+    }
+
+    if let Some(line) = decoder.finish() {
+        on_line(line);
+    }
+}
+
+/// Parse a progress phase and detail from a known tool's output line
+fn detect_progress_hint(line: &str) -> Option<(&'static str, String)> {
+    PROGRESS_PATTERNS.iter().find_map(|(pattern, phase)| {
+        pattern
+            .captures(line)
+            .map(|caps| (*phase, caps[1].trim().to_string()))
+    })
+}
+
+/// Emit a progress hint event if the line matches a known progress pattern
+fn emit_progress_hint(app: &AppHandle, id: i64, line: &str) {
+    if let Some((phase, detail)) = detect_progress_hint(line) {
+        let _ = app.emit(
+            "command_progress_hint",
+            serde_json::json!({
+                "id": id,
+                "phase": phase,
+                "detail": detail
+            }),
+        );
+    }
+}
+
+/// Get the global runtime ceiling from the max_command_runtime_secs preference
+fn max_runtime_secs() -> Option<u64> {
+    u64::try_from(db::get_preference_i64("max_command_runtime_secs", 0))
+        .ok()
+        .filter(|&secs| secs > 0)
+}
+
+// Kept when clearing the environment so the shell itself can still start
+const ESSENTIAL_ENV: &[&str] = &["PATH", "SYSTEMROOT", "COMSPEC"];
+
+/// Apply extra environment variables, optionally starting from a clean environment.
+/// A PATH override is separate from `env` and applied after it, so it always wins;
+/// it replaces PATH entirely, so it must still include the shell.
+fn apply_env(
+    cmd: &mut tokio::process::Command,
+    env: Option<&HashMap<String, String>>,
+    clear_env: bool,
+    path_override: Option<&str>,
+) {
+    if clear_env {
+        cmd.env_clear();
+        for name in ESSENTIAL_ENV {
+            if let Ok(value) = std::env::var(name) {
+                cmd.env(name, value);
+            }
+        }
+    }
+
+    if let Some(env) = env {
+        // Values often hold credentials, so only the names are logged
+        let mut names: Vec<&str> = env.keys().map(String::as_str).collect();
+        names.sort();
+        tracing::info!("Command environment overrides: {}", names.join(", "));
+
+        cmd.envs(env);
+    }
+
+    if let Some(path) = path_override {
+        tracing::info!("Command PATH override: {}", path);
+        cmd.env("PATH", path);
+    }
+}
+
+/// Check the strip_ansi preference, off by default so colors reach capable frontends
+fn strip_ansi_enabled() -> bool {
+    db::get_preference_bool("strip_ansi", false)
+}
+
+/// Default cap on output stored in history, in bytes
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Get the cap on stored output from the max_output_bytes preference, where 0 means no limit
+fn max_output_bytes() -> Option<usize> {
+    let limit = usize::try_from(db::get_preference_i64(
+        "max_output_bytes",
+        DEFAULT_MAX_OUTPUT_BYTES as i64,
+    ))
+    .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+    Some(limit).filter(|&limit| limit > 0)
+}
+
+/// Largest char boundary in `text` at or below `index`
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    if index >= text.len() {
+        return text.len();
+    }
+    (0..=index).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0)
+}
+
+/// Get the fallback timeout from the default_command_timeout preference
+fn default_timeout_secs() -> Option<u64> {
+    db::get_preference("default_command_timeout")
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+}
+
+/// Resolve a command's timeout, where 0 means no limit and the global ceiling always applies
+fn resolve_timeout(requested: Option<u64>) -> Option<u64> {
+    let requested = requested.or_else(default_timeout_secs).filter(|&secs| secs > 0);
+    match (requested, max_runtime_secs()) {
+        (Some(secs), Some(max)) => Some(secs.min(max)),
+        (secs, max) => secs.or(max),
+    }
+}
+
 /// Run a command synchronously and return result (for workflows)
 pub async fn run_command_sync(
     command: &str,
     cwd: Option<&str>,
+    shell: Option<&str>,
+    env: Option<&HashMap<String, String>>,
+    clear_env: bool,
+    path_override: Option<&str>,
+    timeout_secs: Option<u64>,
 ) -> Result<(i32, String, String), String> {
     let working_dir = cwd.unwrap_or(".");
 
-    #[cfg(target_os = "windows")]
-    let mut cmd = Command::new("powershell");
-    #[cfg(target_os = "windows")]
-    cmd.args(["-NoProfile", "-NonInteractive", "-Command", command]);
-    
-    #[cfg(not(target_os = "windows"))]
-    let mut cmd = Command::new("sh");
-    #[cfg(not(target_os = "windows"))]
-    cmd.args(["-c", command]);
-
-    let output = cmd
+    // Use the requested shell, the shell preference, or the OS default
+    let shell = select_shell(shell)?;
+    let mut cmd = shell::build_command(shell, command);
+    apply_env(&mut cmd, env, clear_env, path_override);
+
+    // A process group of its own lets a timeout or cancel stop everything the shell started
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd
         .current_dir(working_dir)
-        .output()
-        .await
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
         .map_err(|e| format!("Failed to execute command: {}", e))?;
+    let stdout_pipe = child.stdout.take();
+    let stderr_pipe = child.stderr.take();
 
-    let exit_code = output.status.code().unwrap_or(-1);
-
-    let stdout = if is_binary_output(&output.stdout) {
-        "[Binary output]".to_string()
-    } else {
-        String::from_utf8_lossy(&output.stdout).to_string()
+    // A cancelled workflow drops this future, which takes the guard and the process tree with it
+    let mut guard = ProcessTreeGuard(Some(child));
+    let limit = max_output_bytes();
+    let strip_ansi = strip_ansi_enabled();
+    // Owned out here so a timeout still has the output read so far
+    let mut stdout = CappedBuffer::new(limit);
+    let mut stderr = CappedBuffer::new(limit);
+    let run = async {
+        let child = guard.0.as_mut().expect("child is reaped only after the run");
+        let (_, _, status) = tokio::join!(
+            read_capped(stdout_pipe, &mut stdout, strip_ansi),
+            read_capped(stderr_pipe, &mut stderr, strip_ansi),
+            child.wait()
+        );
+        status
     };
 
-    let stderr = if is_binary_output(&output.stderr) {
-        "[Binary output]".to_string()
-    } else {
-        String::from_utf8_lossy(&output.stderr).to_string()
+    // The global runtime ceiling caps any per-command timeout
+    let timeout_secs = resolve_timeout(timeout_secs);
+
+    let finished = match timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), run)
+            .await
+            .map_err(|_| secs),
+        None => Ok(run.await),
+    };
+    let status = match finished {
+        Ok(result) => {
+            guard.disarm();
+            result.map_err(|e| format!("Failed to execute command: {}", e))?
+        }
+        Err(secs) => {
+            guard.kill().await;
+            let mut stderr = stderr.contents();
+            if !stderr.is_empty() && !stderr.ends_with('\n') {
+                stderr.push('\n');
+            }
+            stderr.push_str(&format!("Command timed out after {} seconds", secs));
+            return Ok((TIMEOUT_EXIT_CODE, stdout.contents(), stderr));
+        }
     };
 
-    Ok((exit_code, stdout, stderr))
+    Ok((status.code().unwrap_or(-1), stdout.contents(), stderr.contents()))
+}
+
+/// Check if output is binary, allowing for UTF-16 text which is full of zero bytes
+fn looks_binary(data: &[u8]) -> bool {
+    is_binary_output(data) && !encoding::is_utf16(data)
+}
+
+/// Read a child's pipe into the buffer as it's written, keeping only what fits under its limit
+async fn read_capped<R: AsyncRead + Unpin>(
+    pipe: Option<R>,
+    buffer: &mut CappedBuffer,
+    strip_ansi: bool,
+) {
+    let mut binary = false;
+    if let Some(pipe) = pipe {
+        read_decoded_lines(
+            pipe,
+            |line| {
+                if strip_ansi {
+                    buffer.push_line(&encoding::strip_ansi_codes(&line));
+                } else {
+                    buffer.push_line(&line);
+                }
+            },
+            || binary = true,
+        )
+        .await;
+    }
+    if binary {
+        buffer.mark_binary();
+    }
+}
+
+/// Owns a child until it's reaped, killing its whole process tree if dropped first
+struct ProcessTreeGuard(Option<Child>);
+
+impl ProcessTreeGuard {
+    /// Stop guarding a child that exited on its own
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+
+    /// Kill the process tree now and reap the child
+    async fn kill(&mut self) {
+        if let Some(mut child) = self.0.take() {
+            let _ = kill_process_tree(&mut child);
+            let _ = child.wait().await;
+        }
+    }
+}
+
+impl Drop for ProcessTreeGuard {
+    fn drop(&mut self) {
+        if let Some(child) = self.0.as_mut() {
+            let _ = kill_process_tree(child);
+        }
+    }
+}
+
+/// Kill a child along with its descendants, such as a dev server started by the shell
+fn kill_process_tree(child: &mut Child) -> std::io::Result<()> {
+    if let Some(pid) = child.id() {
+        // Not waited on, so killing never blocks the caller
+        #[cfg(windows)]
+        let _ = std::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        // The child leads its own process group, so its id is the group id
+        #[cfg(unix)]
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+
+    // Make sure the shell itself is gone even if the tree kill failed
+    child.start_kill()
 }
 
 /// Kill a running command
 pub fn kill_command(id: i64) -> Result<(), String> {
-    let mut processes = RUNNING_PROCESSES.lock();
+    let process = RUNNING_PROCESSES.lock().remove(&id);
 
-    if let Some(mut child) = processes.remove(&id) {
-        // Try to kill the process
-        match child.start_kill() {
+    if let Some(mut process) = process {
+        EXIT_CODES.lock().insert(id, KILLED_EXIT_CODE);
+
+        // Try to kill the process and everything it started
+        match kill_process_tree(&mut process.child) {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("Failed to kill process: {}", e)),
         }
@@ -241,9 +936,153 @@ pub fn kill_command(id: i64) -> Result<(), String> {
     }
 }
 
+/// Write text to a running command's stdin
+pub async fn send_stdin(id: i64, input: &str) -> Result<(), String> {
+    let stdin = match RUNNING_PROCESSES.lock().get(&id) {
+        Some(process) => process.stdin.clone().ok_or_else(|| {
+            "Command was not started with interactive_input, or its stdin is closed".to_string()
+        })?,
+        None => return Err("Process not found or already completed".to_string()),
+    };
+
+    let mut stdin = stdin.lock().await;
+    stdin
+        .write_all(input.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write to stdin: {}", e))?;
+    stdin
+        .flush()
+        .await
+        .map_err(|e| format!("Failed to write to stdin: {}", e))
+}
+
+/// Close a running command's stdin so it sees end of input
+pub fn close_stdin(id: i64) -> Result<(), String> {
+    match RUNNING_PROCESSES.lock().get_mut(&id) {
+        // Dropping the last handle closes the pipe
+        Some(process) => match process.stdin.take() {
+            Some(_) => Ok(()),
+            None => Err("Command's stdin is not open".to_string()),
+        },
+        None => Err("Process not found or already completed".to_string()),
+    }
+}
+
+/// Kill every running command, returning the ids that were stopped
+pub fn kill_all_commands() -> Vec<i64> {
+    get_running_commands()
+        .into_iter()
+        .filter(|&id| kill_command(id).is_ok())
+        .collect()
+}
+
 /// Get list of running command IDs
 pub fn get_running_commands() -> Vec<i64> {
     RUNNING_PROCESSES.lock().keys().cloned().collect()
 }
 
+/// Remove finished processes from tracking and record their exit codes
+pub fn reap_finished_commands() -> Vec<i64> {
+    let mut processes = RUNNING_PROCESSES.lock();
+    let mut reaped = Vec::new();
+
+    processes.retain(|&id, process| {
+        let exit_code = match process.child.try_wait() {
+            Ok(Some(status)) => status.code().unwrap_or(-1),
+            Ok(None) => return true,
+            Err(_) => -1,
+        };
+
+        EXIT_CODES.lock().insert(id, exit_code);
+        if id > 0 {
+            let _ = db::update_command_history_exit_code(id, exit_code);
+        }
+        reaped.push(id);
+        false
+    });
+
+    reaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_cap() {
+        let mut buffer = CappedBuffer::new(Some(8));
+        buffer.push_line("abc");
+        buffer.push_line("défghi");
+        assert_eq!(buffer.contents(), "abc\ndéf...[truncated 4 bytes]");
+    }
+
+    #[test]
+    fn test_detect_progress_hint() {
+        assert_eq!(
+            detect_progress_hint("   Compiling serde v1.0.200"),
+            Some(("compiling", "serde v1.0.200".to_string()))
+        );
+        assert_eq!(
+            detect_progress_hint("added 120 packages, and audited 121 packages in 3s"),
+            Some(("installed", "120".to_string()))
+        );
+        assert_eq!(
+            detect_progress_hint("Successfully installed requests-2.32.3"),
+            Some(("installed", "requests-2.32.3".to_string()))
+        );
+        assert_eq!(
+            detect_progress_hint("[3/10] Building CXX object main.o"),
+            Some(("step", "Building CXX object main.o".to_string()))
+        );
+        assert_eq!(detect_progress_hint("warning: unused variable"), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_capped() {
+        let output = b"one\ntwo\n\x1b[31mthree\x1b[0m\nfour";
+        let read = |data: &'static [u8], limit, strip_ansi| async move {
+            let mut buffer = CappedBuffer::new(limit);
+            read_capped(Some(data), &mut buffer, strip_ansi).await;
+            buffer.contents()
+        };
+        assert_eq!(read(output, Some(10), true).await, "one\ntwo\nth...[truncated 9 bytes]");
+        assert_eq!(read(output, None, true).await, "one\ntwo\nthree\nfour\n");
+        assert_eq!(read(b"\x00\x01\x02\x03binary", None, false).await, "[Binary output]");
+    }
 
+    #[test]
+    fn test_line_stream_binary() {
+        let mut stream = LineStream::new();
+        assert!(matches!(stream.push(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0"), Decoded::Binary));
+        assert!(matches!(stream.push(b"text\n"), Decoded::Lines(lines) if lines.is_empty()));
+        assert_eq!(stream.finish(), None);
+
+        let mut stream = LineStream::new();
+        assert!(matches!(stream.push(b"ok\n"), Decoded::Lines(lines) if lines == ["ok"]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_process_tree_guard_kills_grandchildren() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .process_group(0)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while stdout.read(&mut byte).await.unwrap() == 1 && byte[0] != b'\n' {
+            line.push(byte[0]);
+        }
+        let grandchild: u32 = String::from_utf8(line).unwrap().trim().parse().unwrap();
+
+        drop(ProcessTreeGuard(Some(child)));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Gone, or at worst a zombie waiting to be reaped
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", grandchild)).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "grandchild still running: {}", stat);
+    }
+}
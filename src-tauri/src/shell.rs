@@ -0,0 +1,454 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::process::Command;
+
+// Numeric literals such as ports, counts and version numbers
+static NUMBER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\d+(?:\.\d+)*\b").unwrap());
+
+/// A shell the runner knows how to invoke
+#[derive(Debug)]
+pub struct ShellSpec {
+    pub name: &'static str,
+    pub program: &'static str,
+    /// Arguments placed before the command string
+    pub args: &'static [&'static str],
+    /// Operator used to run commands one after another
+    pub separator: &'static str,
+}
+
+// Registry of supported shells and their command-string conventions
+static SHELLS: &[ShellSpec] = &[
+    ShellSpec {
+        name: "sh",
+        program: "sh",
+        args: &["-c"],
+        separator: "; ",
+    },
+    ShellSpec {
+        name: "bash",
+        program: "bash",
+        args: &["-c"],
+        separator: "; ",
+    },
+    ShellSpec {
+        name: "zsh",
+        program: "zsh",
+        args: &["-c"],
+        separator: "; ",
+    },
+    ShellSpec {
+        name: "fish",
+        program: "fish",
+        args: &["-c"],
+        separator: "; ",
+    },
+    ShellSpec {
+        name: "powershell",
+        program: "powershell",
+        args: &["-NoProfile", "-NonInteractive", "-Command"],
+        separator: "\n",
+    },
+    ShellSpec {
+        name: "pwsh",
+        program: "pwsh",
+        args: &["-NoProfile", "-NonInteractive", "-Command"],
+        separator: "\n",
+    },
+    ShellSpec {
+        name: "cmd",
+        program: "cmd",
+        args: &["/C"],
+        separator: " & ",
+    },
+];
+
+/// Get the default shell for the current OS
+pub fn default_shell() -> &'static ShellSpec {
+    #[cfg(target_os = "windows")]
+    let name = "powershell";
+    #[cfg(not(target_os = "windows"))]
+    let name = "sh";
+
+    find_shell(name).expect("default shell missing from registry")
+}
+
+/// Look up a shell in the registry by name
+fn find_shell(name: &str) -> Option<&'static ShellSpec> {
+    SHELLS.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+}
+
+/// Resolve a shell by name, falling back to the OS default when unset
+pub fn resolve_shell(name: Option<&str>) -> Result<&'static ShellSpec, String> {
+    let name = match name.map(str::trim) {
+        Some(name) if !name.is_empty() => name,
+        _ => return Ok(default_shell()),
+    };
+
+    let spec = find_shell(name).ok_or_else(|| {
+        let supported: Vec<&str> = SHELLS.iter().map(|s| s.name).collect();
+        format!(
+            "Unknown shell '{}'. Supported shells: {}",
+            name,
+            supported.join(", ")
+        )
+    })?;
+
+    if find_executable(spec.program).is_none() {
+        return Err(format!("Shell '{}' was not found on PATH", spec.name));
+    }
+
+    Ok(spec)
+}
+
+/// Build a process that runs the command string in the given shell
+pub fn build_command(shell: &ShellSpec, command: &str) -> Command {
+    let mut cmd = Command::new(shell.program);
+    cmd.args(shell.args).arg(command);
+    cmd
+}
+
+/// Join commands into one pipeline; every supported shell pipes with `|`
+pub fn join_pipeline(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|command| command.trim())
+        .filter(|command| !command.is_empty())
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Join commands into a single script using the shell's sequencing operator
+pub fn join_commands(shell: &ShellSpec, commands: &[String]) -> String {
+    commands.join(shell.separator)
+}
+
+/// Quote an argument so the shell passes it through literally
+pub fn quote_arg(shell: &ShellSpec, arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=".contains(c));
+
+    match shell.name {
+        _ if plain => arg.to_string(),
+        // cmd has no escape for quotes inside quotes besides doubling them
+        "cmd" => format!("\"{}\"", arg.replace('"', "\"\"")),
+        "powershell" | "pwsh" => format!("'{}'", arg.replace('\'', "''")),
+        "fish" => format!("'{}'", arg.replace('\\', "\\\\").replace('\'', "\\'")),
+        _ => format!("'{}'", arg.replace('\'', "'\\''")),
+    }
+}
+
+/// Split a compound command on `&&`, `||`, `;` and `|`, ignoring quoted text
+pub fn split_segments(command: &str) -> Vec<Range<usize>> {
+    let bytes = command.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut quote: Option<u8> = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(b'"') if b == b'\\' => i += 1,
+            Some(_) => {}
+            None => {
+                let separator_len = match (b, bytes.get(i + 1)) {
+                    (b'&', Some(b'&')) | (b'|', Some(b'|')) => 2,
+                    (b'|', _) | (b';', _) => 1,
+                    (b'\'', _) | (b'"', _) => {
+                        quote = Some(b);
+                        0
+                    }
+                    (b'\\', _) => {
+                        i += 1;
+                        0
+                    }
+                    _ => 0,
+                };
+
+                if separator_len > 0 {
+                    segments.push(start..i);
+                    i += separator_len;
+                    start = i;
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+    segments.push(start..command.len());
+
+    segments
+        .into_iter()
+        .filter(|range| !command[range.clone()].trim().is_empty())
+        .collect()
+}
+
+/// Split a command segment into words, removing quotes and keeping redirections (`>`, `>>`, `>&`) as their own words
+pub fn split_words(segment: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = segment.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            Some(_) => word.push(c),
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    in_word = true;
+                }
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        word.push(next);
+                    }
+                    in_word = true;
+                }
+                '>' => {
+                    // A file descriptor prefix like `2>` or `&>` belongs to the operator
+                    if in_word && !word.chars().all(|c| c.is_ascii_digit() || c == '&') {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    word.clear();
+                    in_word = false;
+
+                    let mut operator = String::from(">");
+                    if let Some(&next) = chars.peek() {
+                        if next == '>' || next == '&' || next == '|' {
+                            operator.push(next);
+                            chars.next();
+                        }
+                    }
+                    words.push(operator);
+                }
+                c if c.is_whitespace() => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                _ => {
+                    word.push(c);
+                    in_word = true;
+                }
+            },
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+// Shell builtins and keywords that never resolve on PATH
+const BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "break", "cd", "command", "continue", "echo", "eval", "exec",
+    "exit", "export", "false", "fg", "for", "if", "jobs", "printf", "pwd", "read", "return",
+    "set", "shift", "source", "test", "time", "trap", "true", "type", "ulimit", "umask",
+    "unalias", "unset", "wait", "while", "cls", "copy", "del", "dir", "md", "move", "rd", "ren",
+];
+
+/// Get the program a command runs, skipping leading `VAR=value` assignments
+pub fn command_program(command: &str) -> Option<String> {
+    let first = split_segments(command).into_iter().next()?;
+    split_words(&command[first])
+        .into_iter()
+        .find(|word| !is_env_assignment(word))
+}
+
+/// Normalize a command so runs that differ only in paths or numbers share a fingerprint
+pub fn command_fingerprint(command: &str) -> String {
+    split_words(command)
+        .iter()
+        .map(|word| normalize_word(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Replace a path with `<path>` and numeric literals with `<n>`, keeping `--flag=` prefixes
+fn normalize_word(word: &str) -> String {
+    if let Some((flag, value)) = word.split_once('=') {
+        if flag.starts_with('-') {
+            return format!("{}={}", flag, normalize_word(value));
+        }
+    }
+
+    if word.contains('/') || word.contains('\\') || word.starts_with('~') {
+        return "<path>".to_string();
+    }
+
+    NUMBER.replace_all(word, "<n>").to_string()
+}
+
+// Builtins whose only effect is on the invoking shell's state
+const STATE_BUILTINS: &[&str] = &["cd", "export", "set", "source", "."];
+
+/// Get the state-changing builtin a command consists of, if it does nothing else
+pub fn state_only_builtin(command: &str) -> Option<String> {
+    let mut builtin = None;
+    for segment in split_segments(command) {
+        let program = split_words(&command[segment])
+            .into_iter()
+            .find(|word| !is_env_assignment(word))?
+            .to_lowercase();
+        if !STATE_BUILTINS.contains(&program.as_str()) {
+            return None;
+        }
+        builtin.get_or_insert(program);
+    }
+    builtin
+}
+
+/// Check if a word is a `VAR=value` assignment
+fn is_env_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// Check if a program is a builtin, a PowerShell cmdlet, or an executable reachable from cwd
+pub fn program_exists(program: &str, cwd: &Path) -> bool {
+    let lower = program.to_lowercase();
+    if BUILTINS.contains(&lower.as_str()) {
+        return true;
+    }
+
+    // Capitalized Verb-Noun names are PowerShell cmdlets
+    if let Some((verb, noun)) = program.split_once('-') {
+        let capitalized = verb.starts_with(|c: char| c.is_ascii_uppercase());
+        if capitalized && !noun.is_empty() && verb.chars().all(|c| c.is_ascii_alphabetic()) {
+            return true;
+        }
+    }
+
+    if program.contains('/') || program.contains('\\') {
+        return cwd.join(program).is_file();
+    }
+
+    find_executable(program).is_some()
+}
+
+/// Find an executable on PATH
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.contains('/') || name.contains('\\') {
+        let path = Path::new(name);
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    #[cfg(target_os = "windows")]
+    let extensions: Vec<String> = std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+        .split(';')
+        .map(|e| e.to_string())
+        .chain(std::iter::once(String::new()))
+        .collect();
+    #[cfg(not(target_os = "windows"))]
+    let extensions = [String::new()];
+
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var).find_map(|dir| {
+        extensions.iter().find_map(|ext| {
+            let candidate = dir.join(format!("{}{}", name, ext));
+            candidate.is_file().then_some(candidate)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_commands() {
+        let commands = vec!["npm install".to_string(), "npm test".to_string()];
+        let sh = find_shell("sh").unwrap();
+        assert_eq!(join_commands(sh, &commands), "npm install; npm test");
+
+        let stages = vec!["grep x file ".to_string(), "sort".to_string(), " uniq -c".to_string()];
+        assert_eq!(join_pipeline(&stages), "grep x file | sort | uniq -c");
+    }
+
+    #[test]
+    fn test_quote_arg() {
+        let sh = find_shell("sh").unwrap();
+        assert_eq!(quote_arg(sh, "src/main.rs"), "src/main.rs");
+        assert_eq!(quote_arg(sh, "it's here"), r#"'it'\''s here'"#);
+        assert_eq!(quote_arg(sh, ""), "''");
+
+        let pwsh = find_shell("pwsh").unwrap();
+        assert_eq!(quote_arg(pwsh, "C:\\My Files\\it's"), r"'C:\My Files\it''s'");
+
+        let fish = find_shell("fish").unwrap();
+        assert_eq!(quote_arg(fish, r"a\b 'c'"), r"'a\\b \'c\''");
+
+        let cmd = find_shell("cmd").unwrap();
+        assert_eq!(quote_arg(cmd, r#"say "hi""#), r#""say ""hi""""#);
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"echo "a b" 2>err.log >> 'out file'"#),
+            vec!["echo", "a b", ">", "err.log", ">>", "out file"]
+        );
+        assert_eq!(split_words("cmd >&2 >|x"), vec!["cmd", ">&", "2", ">|", "x"]);
+    }
+
+    #[test]
+    fn test_command_program() {
+        assert_eq!(command_program("RUST_LOG=debug cargo run && ls"), Some("cargo".to_string()));
+        assert_eq!(command_program("  "), None);
+        assert!(program_exists("cd", Path::new(".")));
+        assert!(program_exists("Get-ChildItem", Path::new(".")));
+        assert!(!program_exists("not-a-real-tool-xyz", Path::new(".")));
+    }
+
+    #[test]
+    fn test_command_fingerprint() {
+        assert_eq!(
+            command_fingerprint("docker run -p 8080:80 -v ./data:/data nginx:1.25"),
+            "docker run -p <n>:<n> -v <path> nginx:<n>"
+        );
+        assert_eq!(
+            command_fingerprint("python3 train.py --epochs=20 --out=~/models"),
+            command_fingerprint("python3 train.py --epochs=5 --out=/tmp/run2")
+        );
+        assert_eq!(command_fingerprint("cat 'C:\\logs\\app.log'"), "cat <path>");
+    }
+
+    #[test]
+    fn test_state_only_builtin() {
+        assert_eq!(state_only_builtin("cd src"), Some("cd".to_string()));
+        assert_eq!(state_only_builtin("export A=1 && source .env"), Some("export".to_string()));
+        assert_eq!(state_only_builtin("cd src && npm test"), None);
+        assert_eq!(state_only_builtin("set -e; make"), None);
+        assert_eq!(state_only_builtin("ls"), None);
+    }
+
+    #[test]
+    fn test_split_segments() {
+        let command = "echo 'a | b' && ls -la | grep x; pwd";
+        let segments: Vec<&str> = split_segments(command)
+            .into_iter()
+            .map(|r| command[r].trim())
+            .collect();
+        assert_eq!(segments, vec!["echo 'a | b'", "ls -la", "grep x", "pwd"]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::db;
+use crate::models::CommandTemplate;
+
+// `{name}` placeholders; a leading `$` marks shell syntax like `${HOME}` instead
+static PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\$?)\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// Save a named template, replacing any existing template with the same name
+pub fn save_template(name: &str, template: &str, description: Option<&str>) -> Result<i64, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Template name cannot be empty".to_string());
+    }
+    if template.trim().is_empty() {
+        return Err("Template cannot be empty".to_string());
+    }
+
+    let template = CommandTemplate {
+        id: None,
+        name: name.to_string(),
+        template: template.to_string(),
+        description: description.map(|s| s.to_string()),
+        placeholders: Vec::new(),
+        created_at: Some(Utc::now().to_rfc3339()),
+    };
+
+    db::upsert_command_template(&template).map_err(|e| e.to_string())
+}
+
+/// Get all saved templates with their placeholder names
+pub fn get_templates() -> Result<Vec<CommandTemplate>, String> {
+    let mut templates = db::get_command_templates().map_err(|e| e.to_string())?;
+    for template in &mut templates {
+        template.placeholders = placeholders(&template.template);
+    }
+    Ok(templates)
+}
+
+/// Delete a template by name
+pub fn delete_template(name: &str) -> Result<(), String> {
+    match db::delete_command_template(name).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("Template '{}' not found", name)),
+    }
+}
+
+/// Render a saved template with the given parameters
+pub fn render_template(name: &str, params: &HashMap<String, String>) -> Result<String, String> {
+    let template = db::get_command_template(name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Template '{}' not found", name))?;
+
+    render(&template.template, params)
+}
+
+/// List the distinct placeholder names in a template, in order of appearance
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for caps in PLACEHOLDER.captures_iter(template) {
+        if caps[1].is_empty() && !names.iter().any(|n| n == &caps[2]) {
+            names.push(caps[2].to_string());
+        }
+    }
+    names
+}
+
+/// Substitute `{name}` placeholders, failing if any parameter is missing
+pub fn render(template: &str, params: &HashMap<String, String>) -> Result<String, String> {
+    let missing: Vec<String> = placeholders(template)
+        .into_iter()
+        .filter(|name| !params.contains_key(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Missing template parameters: {}", missing.join(", ")));
+    }
+
+    Ok(PLACEHOLDER
+        .replace_all(template, |caps: &regex::Captures| {
+            if caps[1].is_empty() {
+                params[&caps[2]].clone()
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template = "docker run -p {port}:{port} -v ${HOME}:/data {image}";
+        assert_eq!(placeholders(template), vec!["port", "image"]);
+
+        let mut params = HashMap::new();
+        params.insert("port".to_string(), "8080".to_string());
+        assert_eq!(
+            render(template, &params).unwrap_err(),
+            "Missing template parameters: image"
+        );
+
+        params.insert("image".to_string(), "nginx".to_string());
+        assert_eq!(
+            render(template, &params).unwrap(),
+            "docker run -p 8080:8080 -v ${HOME}:/data nginx"
+        );
+    }
+}
@@ -1,66 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use chrono::Utc;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use regex::Regex;
+use sha2::{Digest, Sha256};
 use tauri::{AppHandle, Emitter};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
 
 use crate::ai;
+use crate::context;
 use crate::db;
-use crate::models::{Workflow, WorkflowRunResult, WorkflowStep};
+use crate::models::{StepWarning, Workflow, WorkflowExport, WorkflowRunResult, WorkflowStep};
+use crate::redaction;
 use crate::runner;
 
-/// Run a workflow with the given steps
+/// Cancellation signals for running workflows, keyed by run
+static RUNNING_WORKFLOWS: Lazy<Mutex<HashMap<u64, watch::Sender<bool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Next workflow run id
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+// `${name}` references to variables captured by earlier steps
+static VAR_REF: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+/// Run a workflow with the given steps, stopping once it has run for `total_timeout_secs`.
+/// A dry run only reports each step and where it would run.
 pub async fn run_workflow(
     app: AppHandle,
     workflow_id: Option<i64>,
     steps: Vec<WorkflowStep>,
     cwd: Option<String>,
+    total_timeout_secs: Option<u64>,
+    dry_run: bool,
 ) -> Result<WorkflowRunResult, String> {
+    check_run_if(&steps)?;
+
+    // Steps run in depends_on order, which a cycle makes impossible
+    check_no_cycles(&steps)?;
+
     let working_dir = cwd.unwrap_or_else(|| ".".to_string());
     let wf_id = workflow_id.unwrap_or(0);
+    if dry_run {
+        return Ok(dry_run_workflow(&app, wf_id, &steps, &working_dir));
+    }
+    let deadline = total_timeout_secs.map(|secs| Instant::now() + Duration::from_secs(secs));
+    let (cleanup_steps, steps): (Vec<WorkflowStep>, Vec<WorkflowStep>) =
+        steps.into_iter().partition(|step| step.cleanup);
+
+    // Register this run so it can be cancelled
+    let run_id = NEXT_RUN_ID.fetch_add(1, Ordering::SeqCst);
+    let (cancel_tx, mut cancel_rx) = watch::channel(false);
+    RUNNING_WORKFLOWS.lock().insert(run_id, cancel_tx);
 
     let mut steps_completed = 0;
     let mut failed_step = None;
     let mut error_msg = None;
     let mut suggestion = None;
+    let mut timed_out = false;
+    let mut vars: HashMap<String, String> = HashMap::new();
 
-    for step in &steps {
-        // Emit step start event
-        let _ = app.emit(
-            "workflow_step_start",
-            serde_json::json!({
-                "workflow_id": wf_id,
-                "step": step.step,
-                "cmd": step.cmd
-            }),
-        );
-
-        // Determine the working directory for this step
-        let step_cwd = step.cwd.clone().unwrap_or_else(|| working_dir.clone());
-
-        // Run the command synchronously
-        let result = runner::run_command_sync(&step.cmd, Some(&step_cwd)).await;
+    for mut batch in parallel_batches(&steps) {
+        // Nothing more runs once the workflow is cancelled or out of time
+        if timed_out || *cancel_rx.borrow() {
+            break;
+        }
 
-        match result {
-            Ok((exit_code, stdout, stderr)) => {
-                // Emit step complete event
+        // Skip steps whose run_if doesn't match how the workflow has gone so far
+        let failed = failed_step.is_some();
+        batch.retain(|step| {
+            let run_if = step.run_if.as_deref().unwrap_or("on_success");
+            let runs = should_run(run_if, failed);
+            if !runs {
                 let _ = app.emit(
-                    "workflow_step_complete",
+                    "workflow_step_skipped",
                     serde_json::json!({
                         "workflow_id": wf_id,
                         "step": step.step,
-                        "exit_code": exit_code,
-                        "stdout": stdout,
-                        "stderr": stderr
+                        "run_if": run_if
                     }),
                 );
+            }
+            runs
+        });
+        if batch.is_empty() {
+            continue;
+        }
+
+        for step in &batch {
+            // Emit step start event
+            let _ = app.emit(
+                "workflow_step_start",
+                serde_json::json!({
+                    "workflow_id": wf_id,
+                    "step": step.step,
+                    "cmd": step.cmd
+                }),
+            );
+        }
+
+        // Run the batch's commands together; cancelling drops (and kills) them
+        let all_failed = |error: String| -> Vec<Result<(i32, String, String), String>> {
+            batch.iter().map(|_| Err(error.clone())).collect()
+        };
+        let results = if *cancel_rx.borrow() {
+            all_failed("Workflow cancelled".to_string())
+        } else {
+            tokio::select! {
+                results = futures::future::join_all(
+                    batch.iter().map(|step| run_step(&app, wf_id, step, &working_dir, &vars)),
+                ) => results,
+                _ = cancel_rx.changed() => all_failed("Workflow cancelled".to_string()),
+                _ = wait_until(deadline) => {
+                    timed_out = true;
+                    all_failed(format!(
+                        "Workflow timed out after {} seconds",
+                        total_timeout_secs.unwrap_or_default()
+                    ))
+                }
+            }
+        };
+
+        // Every step in the batch reports its result; after a fatal failure only on_failure and always steps run
+        for (step, result) in batch.iter().zip(results) {
+            match result {
+                Ok((exit_code, stdout, stderr)) => {
+                    let step_timed_out = exit_code == runner::TIMEOUT_EXIT_CODE;
+
+                    // Emit step complete event
+                    let _ = app.emit(
+                        "workflow_step_complete",
+                        serde_json::json!({
+                            "workflow_id": wf_id,
+                            "step": step.step,
+                            "exit_code": exit_code,
+                            "stdout": stdout,
+                            "stderr": stderr,
+                            "timed_out": step_timed_out
+                        }),
+                    );
+
+                    if exit_code == 0 {
+                        if let Some(name) = &step.capture_var {
+                            vars.insert(name.clone(), stdout.trim().to_string());
+                        }
+                    }
+                    if exit_code == 0 || step.continue_on_fail {
+                        steps_completed = steps_completed.max(step.step);
+                        continue;
+                    }
+                    // Only the first failure is reported; later ones come from on_failure steps
+                    if failed_step.is_some() {
+                        continue;
+                    }
 
-                if exit_code != 0 && !step.continue_on_fail {
                     // Step failed
                     failed_step = Some(step.step);
                     error_msg = Some(stderr.clone());
 
-                    // Try to get AI suggestion for the error
-                    if let Ok(analysis) =
-                        ai::analyze_error(&stderr, exit_code, &step.cmd, Some(&step_cwd)).await
-                    {
+                    // Try to get AI suggestion for the error; a timeout has nothing to analyze
+                    let step_cwd = resolve_step_cwd(&working_dir, step.cwd.as_deref());
+                    let analysis = if step_timed_out {
+                        None
+                    } else {
+                        ai::analyze_error(&stderr, exit_code, &step.cmd, Some(&step_cwd))
+                            .await
+                            .ok()
+                    };
+                    if let Some(analysis) = analysis {
                         suggestion = Some(analysis.clone());
 
                         let _ = app.emit(
@@ -78,34 +189,54 @@ pub async fn run_workflow(
                             serde_json::json!({
                                 "workflow_id": wf_id,
                                 "step": step.step,
-                                "error": stderr
+                                "error": stderr,
+                                "timed_out": step_timed_out
                             }),
                         );
                     }
-
-                    break;
                 }
+                Err(e) => {
+                    if failed_step.is_some() {
+                        continue;
+                    }
+                    failed_step = Some(step.step);
+                    error_msg = Some(e.clone());
 
-                steps_completed = step.step;
+                    let _ = app.emit(
+                        "workflow_failed",
+                        serde_json::json!({
+                            "workflow_id": wf_id,
+                            "step": step.step,
+                            "error": e
+                        }),
+                    );
+                }
             }
-            Err(e) => {
-                failed_step = Some(step.step);
-                error_msg = Some(e.clone());
+        }
+    }
 
-                let _ = app.emit(
-                    "workflow_failed",
-                    serde_json::json!({
-                        "workflow_id": wf_id,
-                        "step": step.step,
-                        "error": e
-                    }),
-                );
+    if timed_out {
+        let _ = app.emit(
+            "workflow_timeout",
+            serde_json::json!({
+                "workflow_id": wf_id,
+                "step": failed_step,
+                "steps_completed": steps_completed,
+                "timeout_secs": total_timeout_secs
+            }),
+        );
+    }
 
-                break;
-            }
+    // Cleanup steps run however the other steps ended, unless the run was cancelled
+    for step in &cleanup_steps {
+        if *cancel_rx.borrow() {
+            break;
         }
+        run_cleanup_step(&app, wf_id, step, &working_dir, &vars, &mut cancel_rx).await;
     }
 
+    RUNNING_WORKFLOWS.lock().remove(&run_id);
+
     // Update workflow last run time if we have a workflow ID
     if let Some(id) = workflow_id {
         let _ = db::update_workflow_last_run(id, &Utc::now().to_rfc3339());
@@ -130,15 +261,311 @@ pub async fn run_workflow(
         failed_step,
         error: error_msg,
         suggestion,
+        timed_out,
+        dry_run: false,
     })
 }
 
+/// Report every step with its effective working directory without running anything
+fn dry_run_workflow(
+    app: &AppHandle,
+    wf_id: i64,
+    steps: &[WorkflowStep],
+    working_dir: &str,
+) -> WorkflowRunResult {
+    // Steps are listed in the order a real run uses, with cleanup steps last
+    let (cleanup_steps, steps): (Vec<WorkflowStep>, Vec<WorkflowStep>) =
+        steps.iter().cloned().partition(|step| step.cleanup);
+    let ordered = parallel_batches(&steps)
+        .into_iter()
+        .flatten()
+        .chain(cleanup_steps.iter());
+    for step in ordered {
+        let _ = app.emit(
+            "workflow_step_start",
+            serde_json::json!({
+                "workflow_id": wf_id,
+                "step": step.step,
+                "cmd": step.cmd,
+                "cwd": resolve_step_cwd(working_dir, step.cwd.as_deref()),
+                "dry_run": true
+            }),
+        );
+    }
+
+    let steps_completed = steps.iter().map(|step| step.step).max().unwrap_or(0);
+    let _ = app.emit(
+        "workflow_complete",
+        serde_json::json!({
+            "workflow_id": wf_id,
+            "success": true,
+            "steps_completed": steps_completed,
+            "dry_run": true
+        }),
+    );
+
+    WorkflowRunResult {
+        workflow_id: wf_id,
+        success: true,
+        steps_completed,
+        failed_step: None,
+        error: None,
+        suggestion: None,
+        timed_out: false,
+        dry_run: true,
+    }
+}
+
+/// Accepted values for a step's run_if
+const RUN_IF_VALUES: &[&str] = &["on_success", "on_failure", "always"];
+
+/// Reject unknown run_if values, and any run_if but "always" on cleanup steps, which always run
+fn check_run_if(steps: &[WorkflowStep]) -> Result<(), String> {
+    for step in steps {
+        let Some(run_if) = step.run_if.as_deref() else {
+            continue;
+        };
+        if !RUN_IF_VALUES.contains(&run_if) {
+            return Err(format!(
+                "Step {} has an unknown run_if '{}'. Use one of: {}",
+                step.step,
+                run_if,
+                RUN_IF_VALUES.join(", ")
+            ));
+        }
+        if step.cleanup && run_if != "always" {
+            return Err(format!(
+                "Step {} is a cleanup step, which always runs, so it can't use run_if '{}'",
+                step.step, run_if
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check whether a step with the given run_if runs, given whether an earlier step failed
+fn should_run(run_if: &str, failed: bool) -> bool {
+    match run_if {
+        "always" => true,
+        "on_failure" => failed,
+        _ => !failed,
+    }
+}
+
+/// Group steps into batches that run together, in dependency order. Each batch starts
+/// with the earliest step whose dependencies have all run, joined by the other members of
+/// its parallel_group that are also ready; group members that depend on it run later.
+fn parallel_batches(steps: &[WorkflowStep]) -> Vec<Vec<&WorkflowStep>> {
+    let known: HashSet<i32> = steps.iter().map(|step| step.step).collect();
+    let mut done: HashSet<i32> = HashSet::new();
+    let mut remaining: Vec<&WorkflowStep> = steps.iter().collect();
+    let mut batches: Vec<Vec<&WorkflowStep>> = Vec::new();
+
+    // Dependencies outside the workflow can't hold a step back
+    let ready = |step: &WorkflowStep, done: &HashSet<i32>| {
+        step.depends_on
+            .iter()
+            .all(|dep| done.contains(dep) || !known.contains(dep))
+    };
+
+    while !remaining.is_empty() {
+        // run_workflow rejects cycles, but if one slips through run the rest as written
+        let Some(first) = remaining.iter().position(|step| ready(step, &done)) else {
+            batches.extend(remaining.drain(..).map(|step| vec![step]));
+            break;
+        };
+        let leader = remaining.remove(first);
+
+        let mut batch = vec![leader];
+        if let Some(group) = leader.parallel_group {
+            let (members, rest): (Vec<&WorkflowStep>, Vec<&WorkflowStep>) = remaining
+                .into_iter()
+                .partition(|step| step.parallel_group == Some(group) && ready(step, &done));
+            batch.extend(members);
+            remaining = rest;
+        }
+
+        done.extend(batch.iter().map(|step| step.step));
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Run a step, retrying non-zero exits with a growing delay up to the step's retry limit
+async fn run_step(
+    app: &AppHandle,
+    wf_id: i64,
+    step: &WorkflowStep,
+    working_dir: &str,
+    vars: &HashMap<String, String>,
+) -> Result<(i32, String, String), String> {
+    let retries = step.retries.unwrap_or(0);
+    let mut attempt = 0;
+    loop {
+        let result = run_step_once(step, working_dir, vars).await?;
+        if result.0 == 0 || attempt >= retries {
+            return Ok(result);
+        }
+
+        attempt += 1;
+        let delay = retry_delay(
+            step.retry_delay_secs.unwrap_or(DEFAULT_RETRY_DELAY_SECS),
+            attempt,
+        );
+        let _ = app.emit(
+            "workflow_step_retry",
+            serde_json::json!({
+                "workflow_id": wf_id,
+                "step": step.step,
+                "attempt": attempt,
+                "max_retries": retries,
+                "exit_code": result.0,
+                "delay_secs": delay.as_secs()
+            }),
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Default delay before a step's first retry
+const DEFAULT_RETRY_DELAY_SECS: u64 = 2;
+
+/// Delay before the given retry (1-based): the base delay, doubled for each earlier retry
+fn retry_delay(base_secs: u64, attempt: u32) -> Duration {
+    let factor = 1u64 << attempt.saturating_sub(1).min(16);
+    Duration::from_secs(base_secs.saturating_mul(factor))
+}
+
+/// Run a single step's command in its working directory, killing it after its timeout
+async fn run_step_once(
+    step: &WorkflowStep,
+    working_dir: &str,
+    vars: &HashMap<String, String>,
+) -> Result<(i32, String, String), String> {
+    let cmd = substitute_vars(&step.cmd, vars, step.env.as_ref())?;
+    let cwd = step
+        .cwd
+        .as_deref()
+        .map(|cwd| substitute_vars(cwd, vars, step.env.as_ref()))
+        .transpose()?;
+    let step_cwd = resolve_step_cwd(working_dir, cwd.as_deref());
+    runner::run_command_sync(
+        &cmd,
+        Some(&step_cwd),
+        step.shell.as_deref(),
+        step.env.as_ref(),
+        step.clear_env,
+        None,
+        step.timeout_secs,
+    )
+    .await
+}
+
+/// Replace `${name}` references with captured variables, failing on any that aren't defined.
+/// Names set in the step's env or the environment are left for the shell to expand.
+fn substitute_vars(
+    text: &str,
+    vars: &HashMap<String, String>,
+    env: Option<&HashMap<String, String>>,
+) -> Result<String, String> {
+    let is_env_var =
+        |name: &str| env.is_some_and(|env| env.contains_key(name)) || std::env::var_os(name).is_some();
+
+    let mut undefined: Vec<&str> = Vec::new();
+    for caps in VAR_REF.captures_iter(text) {
+        let name = caps.get(1).map_or("", |m| m.as_str());
+        if !vars.contains_key(name) && !is_env_var(name) && !undefined.contains(&name) {
+            undefined.push(name);
+        }
+    }
+    if !undefined.is_empty() {
+        return Err(format!(
+            "Undefined workflow variables: {}. Set them with capture_var on an earlier step",
+            undefined.join(", ")
+        ));
+    }
+
+    Ok(VAR_REF
+        .replace_all(text, |caps: &regex::Captures| match vars.get(&caps[1]) {
+            Some(value) => value.clone(),
+            None => caps[0].to_string(),
+        })
+        .to_string())
+}
+
+/// Wait for a deadline, or forever if there isn't one
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Run a cleanup step, reporting it like any other step without affecting the run's result
+async fn run_cleanup_step(
+    app: &AppHandle,
+    wf_id: i64,
+    step: &WorkflowStep,
+    working_dir: &str,
+    vars: &HashMap<String, String>,
+    cancel_rx: &mut watch::Receiver<bool>,
+) {
+    let _ = app.emit(
+        "workflow_step_start",
+        serde_json::json!({
+            "workflow_id": wf_id,
+            "step": step.step,
+            "cmd": step.cmd
+        }),
+    );
+
+    let result = tokio::select! {
+        result = run_step(app, wf_id, step, working_dir, vars) => result,
+        _ = cancel_rx.changed() => Err("Workflow cancelled".to_string()),
+    };
+
+    let (exit_code, stdout, stderr) = result.unwrap_or_else(|e| (-1, String::new(), e));
+    let _ = app.emit(
+        "workflow_step_complete",
+        serde_json::json!({
+            "workflow_id": wf_id,
+            "step": step.step,
+            "exit_code": exit_code,
+            "stdout": stdout,
+            "stderr": stderr,
+            "timed_out": exit_code == runner::TIMEOUT_EXIT_CODE
+        }),
+    );
+}
+
+/// Resolve a step's cwd, treating relative paths as relative to the workflow's directory
+fn resolve_step_cwd(working_dir: &str, step_cwd: Option<&str>) -> String {
+    let base = Path::new(working_dir);
+    let dir = match step_cwd {
+        Some(dir) => base.join(dir),
+        None => base.to_path_buf(),
+    };
+    context::normalize_path(&dir)
+}
+
+/// Cancel every running workflow, returning how many were signalled
+pub fn cancel_all_workflows() -> usize {
+    let workflows = RUNNING_WORKFLOWS.lock();
+    for cancel in workflows.values() {
+        let _ = cancel.send(true);
+    }
+    workflows.len()
+}
+
 /// Create and save a new workflow
 pub fn create_workflow(
     name: &str,
     description: Option<&str>,
     steps: Vec<WorkflowStep>,
 ) -> Result<i64, String> {
+    check_run_if(&steps)?;
+    check_no_cycles(&steps)?;
+
     let workflow = Workflow {
         id: None,
         name: name.to_string(),
@@ -151,30 +578,545 @@ pub fn create_workflow(
     db::insert_workflow(&workflow).map_err(|e| e.to_string())
 }
 
+/// Replace a saved workflow's name, description, and steps
+pub fn update_workflow(
+    id: i64,
+    name: &str,
+    description: Option<&str>,
+    steps: Vec<WorkflowStep>,
+) -> Result<(), String> {
+    check_run_if(&steps)?;
+    check_no_cycles(&steps)?;
+
+    let definition = serde_json::to_value(&steps).map_err(|e| e.to_string())?;
+    match db::update_workflow(id, name, description, &definition).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("Workflow {} not found", id)),
+    }
+}
+
+/// Delete a saved workflow
+pub fn delete_workflow(id: i64) -> Result<(), String> {
+    match db::delete_workflow(id).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("Workflow {} not found", id)),
+    }
+}
+
+/// Current version of the workflow export format
+const WORKFLOW_EXPORT_VERSION: u32 = 1;
+
+/// Serialize a saved workflow to portable JSON
+pub fn export_workflow(id: i64) -> Result<String, String> {
+    let workflow = db::get_workflow(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Workflow {} not found", id))?;
+    let steps = parse_workflow_steps(workflow.definition)?;
+
+    let export = WorkflowExport {
+        version: WORKFLOW_EXPORT_VERSION,
+        name: workflow.name,
+        description: workflow.description,
+        steps: serde_json::to_value(&steps).map_err(|e| e.to_string())?,
+    };
+    serde_json::to_string_pretty(&export).map_err(|e| e.to_string())
+}
+
+/// Save a workflow from exported JSON. Step directories must stay inside the
+/// project containing `cwd` unless `allow_external_paths` is set.
+pub fn import_workflow(
+    json: &str,
+    cwd: Option<&str>,
+    allow_external_paths: bool,
+) -> Result<i64, String> {
+    let export: WorkflowExport =
+        serde_json::from_str(json).map_err(|e| format!("Invalid workflow file: {}", e))?;
+    if export.version > WORKFLOW_EXPORT_VERSION {
+        return Err(format!(
+            "Workflow file version {} is newer than this app supports ({})",
+            export.version, WORKFLOW_EXPORT_VERSION
+        ));
+    }
+    let steps = parse_workflow_steps(export.steps)?;
+
+    if !allow_external_paths {
+        let cwd = cwd.unwrap_or(".");
+        let root = context::find_project_root(cwd).unwrap_or_else(|| cwd.to_string());
+        let external = external_path_steps(&steps, Path::new(&root));
+        if !external.is_empty() {
+            let external: Vec<String> = external.iter().map(|step| step.to_string()).collect();
+            return Err(format!(
+                "Steps {} use working directories outside {}. Allow external paths to import anyway",
+                external.join(", "),
+                root
+            ));
+        }
+    }
+
+    create_workflow(&export.name, export.description.as_deref(), steps)
+}
+
+/// Steps whose working directory, resolved as a run would, is outside the given root
+fn external_path_steps(steps: &[WorkflowStep], root: &Path) -> Vec<i32> {
+    let root = context::normalize_path(root);
+    steps
+        .iter()
+        .filter(|step| {
+            step.cwd.as_deref().is_some_and(|cwd| {
+                let resolved = resolve_step_cwd(&root, Some(cwd));
+                !context::is_within(&collapse_parent_dirs(Path::new(&resolved)), Path::new(&root))
+            })
+        })
+        .map(|step| step.step)
+        .collect()
+}
+
+/// Fold `.` and `..` out of a path that couldn't be canonicalized, such as one that doesn't exist yet
+fn collapse_parent_dirs(path: &Path) -> PathBuf {
+    let mut collapsed = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                collapsed.pop();
+            }
+            other => collapsed.push(other),
+        }
+    }
+    collapsed
+}
+
+/// A cycle would deadlock at run time, so never save one
+fn check_no_cycles(steps: &[WorkflowStep]) -> Result<(), String> {
+    validate_dependencies(steps).map_err(|cycle| {
+        let cycle: Vec<String> = cycle.iter().map(|step| step.to_string()).collect();
+        format!(
+            "Workflow has circular step dependencies between steps {}",
+            cycle.join(", ")
+        )
+    })
+}
+
 /// Get all saved workflows
 pub fn get_workflows() -> Result<Vec<Workflow>, String> {
     db::get_workflows().map_err(|e| e.to_string())
 }
 
+/// Re-run the safety checks over a saved workflow's steps
+pub fn recheck_workflow_safety(id: i64) -> Result<Vec<StepWarning>, String> {
+    let steps = load_workflow_steps(id)?;
+
+    Ok(steps
+        .iter()
+        .filter_map(|step| {
+            redaction::validate_command(&step.cmd).map(|warning| StepWarning {
+                step: step.step,
+                warning,
+            })
+        })
+        .collect())
+}
+
+/// Compute a stable signature over a workflow's ordered step commands and cwds
+pub fn workflow_signature(id: i64) -> Result<String, String> {
+    let mut steps = load_workflow_steps(id)?;
+    steps.sort_by_key(|step| step.step);
+
+    let mut hasher = Sha256::new();
+    for step in &steps {
+        // JSON encoding keeps the boundaries between fields unambiguous
+        let entry = serde_json::json!([step.cmd, step.cwd]);
+        hasher.update(entry.to_string().as_bytes());
+        hasher.update(b"\n");
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Get a saved workflow's definition JSON verbatim, for editing as text
+pub fn get_workflow_definition_raw(id: i64) -> Result<String, String> {
+    db::get_workflow_definition_raw(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Workflow {} not found", id))
+}
+
+/// Load and parse the steps of a saved workflow
+fn load_workflow_steps(id: i64) -> Result<Vec<WorkflowStep>, String> {
+    let workflow = db::get_workflow(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Workflow {} not found", id))?;
+    parse_workflow_steps(workflow.definition)
+}
+
 /// Parse workflow steps from JSON value
 pub fn parse_workflow_steps(definition: serde_json::Value) -> Result<Vec<WorkflowStep>, String> {
     serde_json::from_value(definition).map_err(|e| format!("Invalid workflow definition: {}", e))
 }
 
+/// Check step dependencies for cycles, returning the step numbers involved in any
+pub fn validate_dependencies(steps: &[WorkflowStep]) -> Result<(), Vec<i32>> {
+    let graph: HashMap<i32, &[i32]> = steps
+        .iter()
+        .map(|step| (step.step, step.depends_on.as_slice()))
+        .collect();
+
+    // Tarjan's algorithm: every strongly connected component with more than one
+    // step (or a step depending on itself) is a cycle
+    struct Tarjan<'a> {
+        graph: &'a HashMap<i32, &'a [i32]>,
+        index: HashMap<i32, usize>,
+        low: HashMap<i32, usize>,
+        stack: Vec<i32>,
+        on_stack: HashSet<i32>,
+        cyclic: Vec<i32>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, step: i32) {
+            let index = self.index.len();
+            self.index.insert(step, index);
+            self.low.insert(step, index);
+            self.stack.push(step);
+            self.on_stack.insert(step);
+
+            // Dependencies on steps that don't exist can't form a cycle
+            let deps = self.graph[&step];
+            for &dep in deps.iter().filter(|dep| self.graph.contains_key(dep)) {
+                if !self.index.contains_key(&dep) {
+                    self.visit(dep);
+                    self.low.insert(step, self.low[&step].min(self.low[&dep]));
+                } else if self.on_stack.contains(&dep) {
+                    self.low.insert(step, self.low[&step].min(self.index[&dep]));
+                }
+            }
+
+            if self.low[&step] == self.index[&step] {
+                let mut component = Vec::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(&member);
+                    component.push(member);
+                    if member == step {
+                        break;
+                    }
+                }
+                if component.len() > 1 || deps.contains(&step) {
+                    self.cyclic.extend(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        graph: &graph,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        cyclic: Vec::new(),
+    };
+
+    let mut order: Vec<i32> = graph.keys().copied().collect();
+    order.sort();
+    for step in order {
+        if !tarjan.index.contains_key(&step) {
+            tarjan.visit(step);
+        }
+    }
+
+    if tarjan.cyclic.is_empty() {
+        Ok(())
+    } else {
+        tarjan.cyclic.sort();
+        Err(tarjan.cyclic)
+    }
+}
+
 /// Generate a workflow from natural language
 pub async fn generate_workflow_from_nl(
     description: &str,
     cwd: Option<&str>,
+    max_steps: Option<usize>,
 ) -> Result<Vec<WorkflowStep>, String> {
     let steps_json = ai::generate_workflow(description, cwd).await?;
 
-    let steps: Vec<WorkflowStep> = steps_json
-        .into_iter()
-        .map(|v| serde_json::from_value(v))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| format!("Failed to parse workflow steps: {}", e))?;
+    let steps = match normalize_generated_steps(steps_json) {
+        Ok(steps) => steps,
+        Err(e) => {
+            // The model occasionally returns nothing usable, so retry once
+            tracing::warn!("Retrying workflow generation: {}", e);
+            let steps_json = ai::generate_workflow(description, cwd).await?;
+            normalize_generated_steps(steps_json)?
+        }
+    };
 
+    check_step_limit(&steps, max_steps.unwrap_or(DEFAULT_MAX_GENERATED_STEPS))?;
     Ok(steps)
 }
 
+/// Default cap on the number of steps in a generated workflow
+const DEFAULT_MAX_GENERATED_STEPS: usize = 10;
+
+/// Reject generated workflows longer than the step limit
+fn check_step_limit(steps: &[WorkflowStep], max_steps: usize) -> Result<(), String> {
+    if steps.len() > max_steps {
+        return Err(format!(
+            "AI generated a workflow with {} steps, more than the limit of {}. Try a narrower description or raise max_steps.",
+            steps.len(),
+            max_steps
+        ));
+    }
+    Ok(())
+}
+
+/// Validate AI-generated steps and renumber them contiguously from 1
+fn normalize_generated_steps(
+    steps_json: Vec<serde_json::Value>,
+) -> Result<Vec<WorkflowStep>, String> {
+    let mut steps = Vec::new();
+    let mut dropped = Vec::new();
+
+    for (index, value) in steps_json.into_iter().enumerate() {
+        match serde_json::from_value::<WorkflowStep>(value) {
+            Ok(step) if !step.cmd.trim().is_empty() => steps.push(step),
+            Ok(_) => dropped.push(format!("entry {} has an empty cmd", index + 1)),
+            Err(e) => dropped.push(format!("entry {} is malformed: {}", index + 1, e)),
+        }
+    }
+
+    if !dropped.is_empty() {
+        tracing::warn!("Dropped invalid workflow steps: {}", dropped.join("; "));
+    }
+
+    if steps.is_empty() {
+        return Err(if dropped.is_empty() {
+            "AI returned an empty workflow".to_string()
+        } else {
+            format!(
+                "AI returned no valid workflow steps: {}",
+                dropped.join("; ")
+            )
+        });
+    }
+
+    // Keep the model's intended order, then close any gaps
+    steps.sort_by_key(|step| step.step);
+    for (index, step) in steps.iter_mut().enumerate() {
+        step.step = index as i32 + 1;
+        step.cmd = step.cmd.trim().to_string();
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(step: i32, depends_on: Vec<i32>) -> WorkflowStep {
+        WorkflowStep {
+            step,
+            cmd: format!("echo {}", step),
+            cwd: None,
+            continue_on_fail: false,
+            shell: None,
+            depends_on,
+            cleanup: false,
+            env: None,
+            clear_env: false,
+            parallel_group: None,
+            run_if: None,
+            capture_var: None,
+            timeout_secs: None,
+            retries: None,
+            retry_delay_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_dependencies() {
+        let steps = vec![step(1, vec![]), step(2, vec![1]), step(3, vec![1, 2, 9])];
+        assert!(validate_dependencies(&steps).is_ok());
+
+        let steps = vec![
+            step(1, vec![3]),
+            step(2, vec![1]),
+            step(3, vec![2]),
+            step(4, vec![4]),
+            step(5, vec![1]),
+        ];
+        assert_eq!(validate_dependencies(&steps), Err(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_parallel_batches() {
+        let mut steps: Vec<WorkflowStep> = (1..=5).map(|n| step(n, vec![])).collect();
+        steps[1].parallel_group = Some(7);
+        steps[2].parallel_group = Some(7);
+        steps[4].parallel_group = Some(7);
+
+        let batches: Vec<Vec<i32>> = parallel_batches(&steps)
+            .into_iter()
+            .map(|batch| batch.iter().map(|s| s.step).collect())
+            .collect();
+        assert_eq!(batches, vec![vec![1], vec![2, 3, 5], vec![4]]);
+    }
 
+    #[test]
+    fn test_parallel_batches_follow_dependencies() {
+        // Step 3 needs step 2 even though they share a group; step 1 needs step 4
+        let mut steps = vec![
+            step(1, vec![4]),
+            step(2, vec![]),
+            step(3, vec![2]),
+            step(4, vec![]),
+        ];
+        steps[1].parallel_group = Some(1);
+        steps[2].parallel_group = Some(1);
+        steps[3].parallel_group = Some(1);
+
+        let batches: Vec<Vec<i32>> = parallel_batches(&steps)
+            .into_iter()
+            .map(|batch| batch.iter().map(|s| s.step).collect())
+            .collect();
+        assert_eq!(batches, vec![vec![2, 4], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn test_check_step_limit() {
+        let steps: Vec<WorkflowStep> = (1..=12).map(|n| step(n, vec![])).collect();
+        assert!(check_step_limit(&steps, 12).is_ok());
+        assert!(check_step_limit(&steps, DEFAULT_MAX_GENERATED_STEPS)
+            .unwrap_err()
+            .starts_with("AI generated a workflow with 12 steps"));
+    }
+
+    #[test]
+    fn test_substitute_vars() {
+        let mut vars = HashMap::new();
+        vars.insert("file".to_string(), "build-42.tar.gz".to_string());
+        assert_eq!(
+            substitute_vars("tar xzf ${file} && rm ${file}", &vars, None).unwrap(),
+            "tar xzf build-42.tar.gz && rm build-42.tar.gz"
+        );
+        assert!(substitute_vars("cp ${file} ${dest}", &vars, None)
+            .unwrap_err()
+            .starts_with("Undefined workflow variables: dest."));
+
+        // Shell variables from the step's env or the environment pass through unchanged
+        let mut env = HashMap::new();
+        env.insert("HOME".to_string(), "/home/ci".to_string());
+        assert_eq!(
+            substitute_vars("cp ${file} ${HOME}/out", &vars, Some(&env)).unwrap(),
+            "cp build-42.tar.gz ${HOME}/out"
+        );
+        assert_eq!(substitute_vars("echo ${PATH}", &vars, None).unwrap(), "echo ${PATH}");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(2, 1), Duration::from_secs(2));
+        assert_eq!(retry_delay(2, 2), Duration::from_secs(4));
+        assert_eq!(retry_delay(2, 4), Duration::from_secs(16));
+        assert_eq!(retry_delay(0, 3), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_external_path_steps() {
+        let root = std::env::current_dir().unwrap();
+        let mut inside = step(1, vec![]);
+        inside.cwd = Some(root.join("src").to_string_lossy().to_string());
+        let mut relative = step(2, vec![]);
+        relative.cwd = Some("src".to_string());
+        let mut outside = step(3, vec![]);
+        outside.cwd = Some(root.parent().unwrap().to_string_lossy().to_string());
+        let mut escaping = step(4, vec![]);
+        escaping.cwd = Some("../../../etc".to_string());
+        let mut missing = step(5, vec![]);
+        missing.cwd = Some("../not-created-yet".to_string());
+        let mut missing_inside = step(6, vec![]);
+        missing_inside.cwd = Some("not-created-yet/../src".to_string());
+
+        assert_eq!(
+            external_path_steps(
+                &[inside, relative, outside, escaping, missing, missing_inside],
+                &root
+            ),
+            vec![3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_should_run() {
+        assert!(should_run("on_success", false));
+        assert!(!should_run("on_success", true));
+        assert!(should_run("on_failure", true));
+        assert!(!should_run("on_failure", false));
+        assert!(should_run("always", true));
+        assert!(should_run("always", false));
+    }
+
+    #[test]
+    fn test_normalize_generated_steps() {
+        assert!(normalize_generated_steps(vec![])
+            .unwrap_err()
+            .contains("empty workflow"));
+
+        let steps = normalize_generated_steps(vec![
+            serde_json::json!({"step": 4, "cmd": " npm install ", "cwd": null}),
+            serde_json::json!({"step": 5, "cmd": "  ", "cwd": null}),
+            serde_json::json!({"cmd": 7}),
+            serde_json::json!({"step": 9, "cmd": "npm test", "cwd": null}),
+        ])
+        .unwrap();
+        let numbered: Vec<(i32, &str)> = steps.iter().map(|s| (s.step, s.cmd.as_str())).collect();
+        assert_eq!(numbered, vec![(1, "npm install"), (2, "npm test")]);
+
+        let err = normalize_generated_steps(vec![serde_json::json!({"step": 1, "cmd": "", "cwd": null})])
+            .unwrap_err();
+        assert!(err.contains("entry 1 has an empty cmd"));
+    }
+
+    #[test]
+    fn test_resolve_step_cwd() {
+        let root = std::env::temp_dir().join(format!("neural-step-cwd-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        let root_str = root.to_string_lossy();
+        let canonical = |path: &Path| context::normalize_path(&path.canonicalize().unwrap());
+
+        // No cwd, or a relative one, resolves against the workflow's directory
+        assert_eq!(resolve_step_cwd(&root_str, None), canonical(&root));
+        assert_eq!(resolve_step_cwd(&root_str, Some("app")), canonical(&root.join("app")));
+        assert_eq!(resolve_step_cwd(&root_str, Some("app/..")), canonical(&root));
+
+        // An absolute cwd replaces the workflow's directory
+        let other = std::env::temp_dir();
+        assert_eq!(
+            resolve_step_cwd(&root.join("app").to_string_lossy(), Some(&other.to_string_lossy())),
+            canonical(&other)
+        );
+
+        // A directory that doesn't exist is left as joined
+        let missing = resolve_step_cwd(&root_str, Some("missing"));
+        assert_eq!(missing, root.join("missing").to_string_lossy());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_check_run_if() {
+        let mut steps = vec![step(1, vec![]), step(2, vec![])];
+        steps[0].run_if = Some("on_failure".to_string());
+        steps[1].cleanup = true;
+        assert!(check_run_if(&steps).is_ok());
+
+        steps[1].run_if = Some("always".to_string());
+        assert!(check_run_if(&steps).is_ok());
+
+        steps[1].run_if = Some("on_success".to_string());
+        assert!(check_run_if(&steps).unwrap_err().contains("cleanup"));
+
+        steps[1].cleanup = false;
+        steps[1].run_if = Some("sometimes".to_string());
+        assert!(check_run_if(&steps).unwrap_err().contains("unknown run_if"));
+    }
+}
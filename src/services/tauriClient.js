@@ -11,17 +11,27 @@ import { listen } from '@tauri-apps/api/event';
 /**
  * Convert natural language to shell command(s)
  */
-export async function nlToCmd(text, cwd = null) {
-  return invoke('nl_to_cmd', { text, cwd });
+export async function nlToCmd(text, cwd = null, joinCommands = false, includeRecentHistory = false) {
+  return invoke('nl_to_cmd', { text, cwd, joinCommands, includeRecentHistory });
 }
 
 // ============ Command Execution ============
 
 /**
- * Run a shell command
+ * Run a shell command (with dryRun, only emits command_dry_run describing what would run).
+ * A timeoutSecs of 0 disables the default_command_timeout preference.
+ * With interactiveInput, stdin stays open for sendStdin/closeStdin.
+ * pathOverride replaces PATH entirely and is applied after env.
  */
-export async function runCommand(command, cwd = null, generatedByAi = false, force = false) {
-  return invoke('run_command', { command, cwd, generatedByAi, force });
+export async function runCommand(command, cwd = null, generatedByAi = false, force = false, saveHistory = true, mergeOutput = false, dryRun = false, timeoutSecs = null, env = null, clearEnv = false, shell = null, interactiveInput = false, pathOverride = null) {
+  return invoke('run_command', { command, cwd, generatedByAi, force, saveHistory, mergeOutput, dryRun, timeoutSecs, env, clearEnv, shell, interactiveInput, pathOverride });
+}
+
+/**
+ * Run a command and wait for its exit code and output
+ */
+export async function runCommandBlocking(command, cwd = null, env = null, timeout = null, force = false) {
+  return invoke('run_command_blocking', { command, cwd, env, timeout, force });
 }
 
 /**
@@ -31,6 +41,48 @@ export async function killCommand(id) {
   return invoke('kill_command', { id });
 }
 
+/**
+ * Kill every running command
+ */
+export async function killAllCommands() {
+  return invoke('kill_all_commands');
+}
+
+/**
+ * Write text to a running command's stdin (requires interactiveInput)
+ */
+export async function sendStdin(id, input) {
+  return invoke('send_stdin', { id, input });
+}
+
+/**
+ * Close a running command's stdin to signal end of input
+ */
+export async function closeStdin(id) {
+  return invoke('close_stdin', { id });
+}
+
+/**
+ * Join separate commands (e.g. a response with pipeline set) into a single pipeline
+ */
+export async function joinAsPipeline(commands) {
+  return invoke('join_as_pipeline', { commands });
+}
+
+/**
+ * Quote an argument (e.g. a user-supplied path) for the active shell
+ */
+export async function quoteForShell(arg, shell = null) {
+  return invoke('quote_for_shell', { arg, shell });
+}
+
+/**
+ * Stop all running commands, AI requests and workflows
+ */
+export async function abortAll() {
+  return invoke('abort_all');
+}
+
 /**
  * Get list of running command IDs
  */
@@ -38,6 +90,13 @@ export async function getRunningCommands() {
   return invoke('get_running_commands');
 }
 
+/**
+ * Stop tracking finished commands, returning the reaped IDs
+ */
+export async function reapFinishedCommands() {
+  return invoke('reap_finished_commands');
+}
+
 // ============ Context ============
 
 /**
@@ -54,6 +113,13 @@ export async function findProjectRoot(start = null) {
   return invoke('find_project_root', { start });
 }
 
+/**
+ * Get recently used directories with their project types
+ */
+export async function getRecentProjects(limit = 10) {
+  return invoke('get_recent_projects', { limit });
+}
+
 // ============ AI Features ============
 
 /**
@@ -64,10 +130,31 @@ export async function analyzeError(stderr, exitCode, command, cwd = null) {
 }
 
 /**
- * Explain a command in detail
+ * Explain an error in plain language, without fixes
+ */
+export async function explainError(stderr, command, cwd = null) {
+  return invoke('explain_error', { stderr, command, cwd });
+}
+
+/**
+ * Explain a command in detail, for a 'beginner' (default), 'intermediate' or 'expert' audience
+ */
+export async function explainCommand(command, cwd = null, explainFormat = null, streamId = null, audience = null) {
+  return invoke('explain_command', { command, cwd, explainFormat, streamId, audience });
+}
+
+/**
+ * Compare two commands and get a recommendation
+ */
+export async function compareCommands(cmdA, cmdB, cwd = null) {
+  return invoke('compare_commands', { cmdA, cmdB, cwd });
+}
+
+/**
+ * Get the kind of an AI error message ('QuotaExceeded', 'BillingError' or null)
  */
-export async function explainCommand(command, cwd = null) {
-  return invoke('explain_command', { command, cwd });
+export async function getAiErrorKind(error) {
+  return invoke('get_ai_error_kind', { error });
 }
 
 /**
@@ -99,12 +186,26 @@ export async function setOpenaiApiKey(key) {
 }
 
 /**
- * Set AI provider (gemini or openai)
+ * Set AI provider (gemini, openai or ollama)
  */
 export async function setAiProvider(provider) {
   return invoke('set_ai_provider', { provider });
 }
 
+/**
+ * Switch AI provider and model together
+ */
+export async function setAiProviderAndModel(provider, model) {
+  return invoke('set_ai_provider_and_model', { provider, model });
+}
+
+/**
+ * Get the selected AI provider and model
+ */
+export async function getAiConfig() {
+  return invoke('get_ai_config');
+}
+
 /**
  * Set AI model
  */
@@ -112,6 +213,48 @@ export async function setAiModel(model) {
   return invoke('set_ai_model', { model });
 }
 
+/**
+ * List the models each AI provider offers
+ */
+export async function listAvailableModels() {
+  return invoke('list_available_models');
+}
+
+/**
+ * Get deprecated model names and their replacements
+ */
+export async function getModelMigrations() {
+  return invoke('get_model_migrations');
+}
+
+/**
+ * Get the endpoint AI requests are sent to
+ */
+export async function getEffectiveEndpoints() {
+  return invoke('get_effective_endpoints');
+}
+
+/**
+ * Get the features a model supports (defaults to the selected model)
+ */
+export async function getModelCapabilities(model = null) {
+  return invoke('get_model_capabilities', { model });
+}
+
+/**
+ * Get suggested temperature, max_tokens and context window for a model
+ */
+export async function getModelDefaults(model) {
+  return invoke('get_model_defaults', { model });
+}
+
+/**
+ * Export all stored AI prompts and responses to a JSON file
+ */
+export async function exportAiLog(path, redacted = false) {
+  return invoke('export_ai_log', { path, redacted });
+}
+
 /**
  * Clear API key for current provider
  */
@@ -124,8 +267,8 @@ export async function clearApiKey() {
 /**
  * Run a workflow
  */
-export async function runWorkflow(definition, cwd = null, workflowId = null) {
-  return invoke('run_workflow', { definition, cwd, workflowId });
+export async function runWorkflow(definition, cwd = null, workflowId = null, totalTimeoutSecs = null, dryRun = false) {
+  return invoke('run_workflow', { definition, cwd, workflowId, totalTimeoutSecs, dryRun });
 }
 
 /**
@@ -135,6 +278,34 @@ export async function createWorkflow(name, description, steps) {
   return invoke('create_workflow', { name, description, steps });
 }
 
+/**
+ * Update a saved workflow's name, description, and steps
+ */
+export async function updateWorkflow(id, name, description, steps) {
+  return invoke('update_workflow', { id, name, description, steps });
+}
+
+/**
+ * Delete a saved workflow
+ */
+export async function deleteWorkflow(id) {
+  return invoke('delete_workflow', { id });
+}
+
+/**
+ * Export a saved workflow as portable JSON
+ */
+export async function exportWorkflow(id) {
+  return invoke('export_workflow', { id });
+}
+
+/**
+ * Import a workflow from exported JSON
+ */
+export async function importWorkflow(json, cwd = null, allowExternalPaths = false) {
+  return invoke('import_workflow', { json, cwd, allowExternalPaths });
+}
+
 /**
  * Get all saved workflows
  */
@@ -143,10 +314,75 @@ export async function getWorkflows() {
 }
 
 /**
- * Generate a workflow from natural language
+ * Get a saved workflow's definition JSON exactly as stored
  */
-export async function generateWorkflow(description, cwd = null) {
-  return invoke('generate_workflow', { description, cwd });
+export async function getWorkflowDefinitionRaw(id) {
+  return invoke('get_workflow_definition_raw', { id });
+}
+
+/**
+ * Check a workflow for circular step dependencies (rejects with the steps in any cycle)
+ */
+export async function validateWorkflowDependencies(definition) {
+  return invoke('validate_workflow_dependencies', { definition });
+}
+
+/**
+ * Re-check a saved workflow's steps against the safety rules
+ */
+export async function recheckWorkflowSafety(id) {
+  return invoke('recheck_workflow_safety', { id });
+}
+
+/**
+ * Get a stable signature of a saved workflow's steps
+ */
+export async function workflowSignature(id) {
+  return invoke('workflow_signature', { id });
+}
+
+/**
+ * Generate a workflow from natural language, rejecting results longer than maxSteps (default 10)
+ */
+export async function generateWorkflow(description, cwd = null, maxSteps = null) {
+  return invoke('generate_workflow', { description, cwd, maxSteps });
+}
+
+/**
+ * Regenerate one step of a workflow with AI
+ */
+export async function regenerateWorkflowStep(description, existingSteps, stepNumber, cwd = null) {
+  return invoke('regenerate_workflow_step', { description, existingSteps, stepNumber, cwd });
+}
+
+// ============ Command Templates ============
+
+/**
+ * Save a named command template with {placeholders}
+ */
+export async function saveTemplate(name, template, description = null) {
+  return invoke('save_template', { name, template, description });
+}
+
+/**
+ * Get all saved command templates
+ */
+export async function getTemplates() {
+  return invoke('get_templates');
+}
+
+/**
+ * Delete a command template
+ */
+export async function deleteTemplate(name) {
+  return invoke('delete_template', { name });
+}
+
+/**
+ * Render a command template with parameters
+ */
+export async function renderTemplate(name, params) {
+  return invoke('render_template', { name, params });
 }
 
 // ============ History & Preferences ============
@@ -158,6 +394,69 @@ export async function getHistory(limit = 100, offset = 0) {
   return invoke('get_history', { limit, offset });
 }
 
+/**
+ * Search command history by command text and output
+ */
+export async function searchCommandHistory(query, limit = 100, offset = 0) {
+  return invoke('search_command_history', { query, limit, offset });
+}
+
+/**
+ * Get command history for a single project
+ */
+export async function getHistoryForProject(root, limit = 100, offset = 0) {
+  return invoke('get_history_for_project', { root, limit, offset });
+}
+
+/**
+ * Get the most frequently run commands, grouped by normalized fingerprint
+ */
+export async function getTopCommands(limit = 10) {
+  return invoke('get_top_commands', { limit });
+}
+
+/**
+ * Get history totals, failure and AI-generated counts, and the most frequent commands
+ */
+export async function getHistoryStats() {
+  return invoke('get_history_stats');
+}
+
+/**
+ * Get a history entry's stdout and stderr as a single log
+ */
+export async function getCommandOutput(id) {
+  return invoke('get_command_output', { id });
+}
+
+/**
+ * Mark a history entry as sensitive so it's never sent to the AI as context
+ */
+export async function markCommandSensitive(id, sensitive) {
+  return invoke('mark_command_sensitive', { id, sensitive });
+}
+
+/**
+ * Delete a history entry along with its AI suggestions
+ */
+export async function deleteCommandHistory(id) {
+  return invoke('delete_command_history', { id });
+}
+
+/**
+ * Delete all command history
+ */
+export async function clearCommandHistory() {
+  return invoke('clear_command_history');
+}
+
+/**
+ * Check recent history for entries whose directory or program no longer exists
+ */
+export async function auditHistoryValidity(limit = 100) {
+  return invoke('audit_history_validity', { limit });
+}
+
 /**
  * Get AI suggestions for a command
  */
@@ -165,6 +464,55 @@ export async function getSuggestionsForCommand(commandId) {
   return invoke('get_suggestions_for_command', { commandId });
 }
 
+/**
+ * Get AI suggestions for a command, grouped by type
+ */
+export async function getSuggestionsGrouped(commandId) {
+  return invoke('get_suggestions_grouped', { commandId });
+}
+
+/**
+ * Get commands that were blocked by the safety checks
+ */
+export async function getBlockedCommands(limit = 100) {
+  return invoke('get_blocked_commands', { limit });
+}
+
+/**
+ * Find suggestions and history rows left behind by deleted or crashed commands
+ */
+export async function findOrphanedRecords(olderThanHours = null) {
+  return invoke('find_orphaned_records', { olderThanHours });
+}
+
+/**
+ * Clean up orphaned suggestions and history rows
+ */
+export async function cleanupOrphanedRecords(olderThanHours = null) {
+  return invoke('cleanup_orphaned_records', { olderThanHours });
+}
+
+/**
+ * Time representative database queries and check which indexes exist
+ */
+export async function diagnoseDbPerformance() {
+  return invoke('diagnose_db_performance');
+}
+
+/**
+ * Save a copy of the database to a new file
+ */
+export async function backupDatabase(destPath) {
+  return invoke('backup_database', { destPath });
+}
+
+/**
+ * Replace the database with a backup file
+ */
+export async function restoreDatabase(srcPath) {
+  return invoke('restore_database', { srcPath });
+}
+
 /**
  * Get a preference value
  */
@@ -179,6 +527,48 @@ export async function setPreference(key, value) {
   return invoke('set_preference', { key, value });
 }
 
+/**
+ * Get a boolean preference, or the default if unset
+ */
+export async function getPreferenceBool(key, defaultValue = false) {
+  return invoke('get_preference_bool', { key, default: defaultValue });
+}
+
+/**
+ * Get an integer preference, or the default if unset
+ */
+export async function getPreferenceInt(key, defaultValue = 0) {
+  return invoke('get_preference_i64', { key, default: defaultValue });
+}
+
+/**
+ * Get a floating-point preference, or the default if unset
+ */
+export async function getPreferenceFloat(key, defaultValue = 0) {
+  return invoke('get_preference_f64', { key, default: defaultValue });
+}
+
+/**
+ * Set a boolean preference
+ */
+export async function setPreferenceBool(key, value) {
+  return invoke('set_preference_bool', { key, value });
+}
+
+/**
+ * Set an integer preference
+ */
+export async function setPreferenceInt(key, value) {
+  return invoke('set_preference_i64', { key, value });
+}
+
+/**
+ * Set a floating-point preference
+ */
+export async function setPreferenceFloat(key, value) {
+  return invoke('set_preference_f64', { key, value });
+}
+
 /**
  * Get all preferences
  */
@@ -195,6 +585,20 @@ export async function validateCommand(command) {
   return invoke('validate_command', { command });
 }
 
+/**
+ * Validate each segment of a compound command
+ */
+export async function validatePipeline(command) {
+  return invoke('validate_pipeline', { command });
+}
+
+/**
+ * Check which existing files a command would overwrite
+ */
+export async function checkOverwrites(command, cwd = null) {
+  return invoke('check_overwrites', { command, cwd });
+}
+
 /**
  * Check if command is interactive
  */
@@ -209,6 +613,27 @@ export async function redactSensitive(text) {
   return invoke('redact_sensitive', { text });
 }
 
+/**
+ * Detect the kinds of secrets in a text, with a count for each
+ */
+export async function detectSecrets(text) {
+  return invoke('detect_secrets', { text });
+}
+
+/**
+ * Check that a custom redaction regex compiles
+ */
+export async function validateRedactionPattern(pattern) {
+  return invoke('validate_redaction_pattern', { pattern });
+}
+
+/**
+ * Find the byte ranges of secrets in a text, for highlighting
+ */
+export async function locateSensitive(text) {
+  return invoke('locate_sensitive', { text });
+}
+
 // ============ Event Listeners ============
 
 /**
@@ -225,6 +650,13 @@ export function onCommandStderr(callback) {
   return listen('command_stderr', (event) => callback(event.payload));
 }
 
+/**
+ * Listen for merged stdout/stderr output, tagged with its source
+ */
+export function onCommandOutput(callback) {
+  return listen('command_output', (event) => callback(event.payload));
+}
+
 /**
  * Listen for command exit
  */
@@ -239,6 +671,20 @@ export function onCommandStarted(callback) {
   return listen('command_started', (event) => callback(event.payload));
 }
 
+/**
+ * Listen for progress hints from long-running installs and builds
+ */
+export function onCommandProgressHint(callback) {
+  return listen('command_progress_hint', (event) => callback(event.payload));
+}
+
+/**
+ * Listen for streamed AI tokens, tagged with the request's stream id
+ */
+export function onAiToken(callback) {
+  return listen('ai_token', (event) => callback(event.payload));
+}
+
 /**
  * Listen for error suggestion
  */
@@ -254,7 +700,7 @@ export function onWorkflowStepStart(callback) {
 }
 
 /**
- * Listen for workflow step complete
+ * Listen for workflow step complete; `timed_out` is set when the step hit its timeout
  */
 export function onWorkflowStepComplete(callback) {
   return listen('workflow_step_complete', (event) => callback(event.payload));